@@ -4,12 +4,29 @@ use cozy_chess::*;
 use tantabus::eval::Eval;
 
 pub struct AnalyzedGame {
+    pub start_pos: Board,
     pub opening_moves: u8,
     pub moves: Vec<Move>,
     pub evals: Vec<Eval>,
+    /// Whether each eval came from a tablebase probe rather than a search.
+    pub tablebase_evals: Vec<bool>,
     pub winner: Option<Color>
 }
 
+/// Every game starts with these bytes and then the format version.
+/// Games written before the header have no start position or tablebase flags:
+/// they start straight away with the opening move count, and are played from the standard start position.
+/// They're still read, with every tablebase flag unset. Their opening move count is never `0xFF`,
+/// so they can't be mistaken for a header.
+/// The header is per game rather than per file so that files can still be concatenated.
+const MAGIC: [u8; 2] = [0xFF, b'T'];
+const VERSION: u8 = 1;
+
+/// The start position is stored as a length-prefixed Shredder FEN, so it survives Chess960 castling.
+fn start_pos_fen(board: &Board) -> String {
+    format!("{:#}", board)
+}
+
 fn pack_move(mv: Move) -> u16 {
     let mut packed = 0;
     packed = (packed << 6) | mv.from as u16;
@@ -41,6 +58,11 @@ fn unpack_move(mut packed: u16) -> Move {
 
 pub fn write_analyzed_game(analysis: &AnalyzedGame, out: &mut impl Write) -> std::io::Result<()> {
     assert_eq!(analysis.moves.len() - analysis.opening_moves as usize, analysis.evals.len());
+    out.write_all(&MAGIC)?;
+    out.write_all(&[VERSION])?;
+    let fen = start_pos_fen(&analysis.start_pos);
+    out.write_all(&[fen.len() as u8])?;
+    out.write_all(fen.as_bytes())?;
     out.write_all(&[analysis.opening_moves])?;
     out.write_all(&(analysis.moves.len() as u16).to_le_bytes())?;
     for &mv in &analysis.moves {
@@ -49,6 +71,13 @@ pub fn write_analyzed_game(analysis: &AnalyzedGame, out: &mut impl Write) -> std
     for &eval in &analysis.evals {
         out.write_all(&eval.to_bytes())?;
     }
+    assert_eq!(analysis.tablebase_evals.len(), analysis.evals.len());
+    for flags in analysis.tablebase_evals.chunks(8) {
+        let packed = flags.iter()
+            .enumerate()
+            .fold(0, |packed, (i, &flag)| packed | ((flag as u8) << i));
+        out.write_all(&[packed])?;
+    }
     out.write_all(&[analysis.winner.map_or(2, |c| c as u8)])?;
     Ok(())
 }
@@ -64,8 +93,33 @@ pub fn read_analyzed_game(reader: &mut impl Read) -> std::io::Result<Option<Anal
             }}
         }
     
-        let opening_moves = read_num!(u8);
+        let first_byte = read_num!(u8);
         started_reading = true;
+        let has_header = first_byte == MAGIC[0];
+        if has_header {
+            if read_num!(u8) != MAGIC[1] {
+                return Err(std::io::Error::new(std::io::ErrorKind::InvalidData, "Invalid game header"));
+            }
+            let version = read_num!(u8);
+            if version != VERSION {
+                return Err(std::io::Error::new(
+                    std::io::ErrorKind::InvalidData,
+                    format!("Unsupported game format version {}", version)
+                ));
+            }
+        }
+
+        let (start_pos, opening_moves) = if has_header {
+            let fen_len = read_num!(u8) as usize;
+            let mut fen = vec![0; fen_len];
+            reader.read_exact(&mut fen)?;
+            let start_pos = String::from_utf8(fen).ok()
+                .and_then(|fen| Board::from_fen(&fen, true).ok())
+                .ok_or_else(|| std::io::Error::new(std::io::ErrorKind::InvalidData, "Invalid start position"))?;
+            (start_pos, read_num!(u8))
+        } else {
+            (Board::default(), first_byte)
+        };
     
         let moves_len = read_num!(u16) as usize;
         let mut moves = Vec::with_capacity(moves_len);
@@ -80,6 +134,19 @@ pub fn read_analyzed_game(reader: &mut impl Read) -> std::io::Result<Option<Anal
             reader.read_exact(&mut eval)?;
             evals.push(Eval::from_bytes(eval));
         }
+        let mut tablebase_evals = Vec::with_capacity(evals_len);
+        if has_header {
+            for _ in 0..evals_len.div_ceil(8) {
+                let packed = read_num!(u8);
+                for i in 0..8 {
+                    if tablebase_evals.len() < evals_len {
+                        tablebase_evals.push(packed & (1 << i) != 0);
+                    }
+                }
+            }
+        } else {
+            tablebase_evals.resize(evals_len, false);
+        }
     
         let winner = read_num!(u8);
         let winner = if winner != 2 {
@@ -89,16 +156,77 @@ pub fn read_analyzed_game(reader: &mut impl Read) -> std::io::Result<Option<Anal
         };
         
         Ok(AnalyzedGame {
+            start_pos,
             opening_moves,
             moves,
             evals,
+            tablebase_evals,
             winner,
         })
     })();
 
     if !started_reading {
-        return Ok(None);
+        // Running out of input before a game starts is the normal end of the file.
+        return match result {
+            Err(error) if error.kind() != std::io::ErrorKind::UnexpectedEof => Err(error),
+            _ => Ok(None)
+        };
     }
     
     result.map(Some)
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn moves(moves: &[&str]) -> Vec<Move> {
+        moves.iter().map(|mv| mv.parse().unwrap()).collect()
+    }
+
+    #[test]
+    fn read_headerless_game() {
+        // A game in the format from before the header: one opening move, then one searched move.
+        let mut bytes = vec![1];
+        bytes.extend_from_slice(&2u16.to_le_bytes());
+        for mv in moves(&["e2e4", "e7e5"]) {
+            bytes.extend_from_slice(&pack_move(mv).to_le_bytes());
+        }
+        bytes.extend_from_slice(&Eval::cp(-30).to_bytes());
+        bytes.push(Color::White as u8);
+
+        let mut reader = bytes.as_slice();
+        let game = read_analyzed_game(&mut reader).unwrap().unwrap();
+        assert_eq!(game.start_pos, Board::default());
+        assert_eq!(game.opening_moves, 1);
+        assert_eq!(game.moves, moves(&["e2e4", "e7e5"]));
+        assert_eq!(game.evals, [Eval::cp(-30)]);
+        assert_eq!(game.tablebase_evals, [false]);
+        assert_eq!(game.winner, Some(Color::White));
+        assert!(read_analyzed_game(&mut reader).unwrap().is_none());
+    }
+
+    #[test]
+    fn round_trip() {
+        let start_pos = Board::from_fen("nrbkqrbn/pppppppp/8/8/8/8/PPPPPPPP/NRBKQRBN w FBfb - 0 1", true).unwrap();
+        let game = AnalyzedGame {
+            start_pos: start_pos.clone(),
+            opening_moves: 1,
+            moves: moves(&["e2e4", "e7e5", "d2d4"]),
+            evals: vec![Eval::cp(15), Eval::mate_in(3)],
+            tablebase_evals: vec![false, true],
+            winner: None
+        };
+        let mut bytes = Vec::new();
+        write_analyzed_game(&game, &mut bytes).unwrap();
+        assert_eq!(bytes[..3], [MAGIC[0], MAGIC[1], VERSION]);
+
+        let decoded = read_analyzed_game(&mut bytes.as_slice()).unwrap().unwrap();
+        assert_eq!(decoded.start_pos, start_pos);
+        assert_eq!(decoded.opening_moves, game.opening_moves);
+        assert_eq!(decoded.moves, game.moves);
+        assert_eq!(decoded.evals, game.evals);
+        assert_eq!(decoded.tablebase_evals, game.tablebase_evals);
+        assert_eq!(decoded.winner, game.winner);
+    }
+}
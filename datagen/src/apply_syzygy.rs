@@ -1,7 +1,9 @@
+use std::collections::BTreeMap;
 use std::fs::File;
-use std::io::{BufReader, BufWriter};
+use std::io::{Write, BufReader, BufWriter};
 use std::path::PathBuf;
-use std::sync::Arc;
+use std::sync::{Arc, Mutex};
+use std::sync::mpsc::sync_channel;
 use std::sync::atomic::{AtomicBool, Ordering};
 
 use clap::Args;
@@ -9,7 +11,8 @@ use cozy_chess::*;
 use cozy_syzygy::{Tablebase, Wdl};
 use tantabus::eval::Eval;
 
-use crate::analyzed_game::{read_analyzed_game, write_analyzed_game};
+use crate::default_threads;
+use crate::analyzed_game::{AnalyzedGame, read_analyzed_game, write_analyzed_game};
 
 #[derive(Debug, Args)]
 /// Apply syzygy evals to a set of games
@@ -26,18 +29,81 @@ pub struct ApplySyzygyConfig {
     #[clap(short, long)]
     out_file: PathBuf,
 
-    /// Magnitude of the eval for a win or a loss
+    /// Thread count
+    #[clap(long, default_value_t = default_threads())]
+    threads: u32,
+
+    /// Write games in the same order as the input
+    #[clap(long)]
+    preserve_order: bool,
+
+    /// Magnitude of the eval for a win or a loss.
+    /// ExtractPos drops evals above its max eval, which is also 20000 by default.
     #[clap(long, default_value_t = 20_000)]
     win_score: i16,
     
     /// Magnitude of the eval for a cursed win or a blessed loss
     #[clap(long, default_value_t = 0)]
-    cursed_win_score: i16
+    cursed_win_score: i16,
+
+    /// Keep the search eval for wins and losses if it's at least this decisive,
+    /// instead of replacing it with the win score.
+    /// This keeps tablebase positions on the engine's scale so they don't dominate training.
+    #[clap(long)]
+    soft_win_score: Option<i16>,
+
+    /// Probe DTZ to score cursed wins and blessed losses by how far past the 50 move rule they are.
+    /// Requires the DTZ tables.
+    #[clap(long)]
+    use_dtz: bool,
+
+    /// Magnitude of the eval for a cursed win or blessed loss right at the 50 move boundary when using DTZ.
+    /// Scores fall off linearly to the cursed win score 100 plies past it.
+    #[clap(long, default_value_t = 200)]
+    boundary_cursed_win_score: i16
 }
 
-pub fn run_apply_syzygy(config: &ApplySyzygyConfig, abort: &Arc<AtomicBool>) {
-    let init_pos = Board::default();
+/// Score a cursed win by how many plies past the 50 move rule the win is.
+fn cursed_win_score(config: &ApplySyzygyConfig, tablebase: &Tablebase, board: &Board) -> i16 {
+    if !config.use_dtz {
+        return config.cursed_win_score;
+    }
+    let dtz = match tablebase.probe_dtz(board) {
+        Some((dtz, _)) => dtz.0 as i32,
+        None => return config.cursed_win_score
+    };
+    let plies_past_boundary = (dtz.abs() + board.halfmove_clock() as i32 - 100).clamp(0, 100);
+    let near = config.boundary_cursed_win_score as i32;
+    let far = config.cursed_win_score as i32;
+    (near - (near - far) * plies_past_boundary / 100) as i16
+}
+
+fn apply_syzygy(config: &ApplySyzygyConfig, tablebase: &Tablebase, game: &mut AnalyzedGame) {
+    let mut board = game.start_pos.clone();
+    for i in 0..game.moves.len() {
+        if i > game.opening_moves as usize && board.occupied().len() <= tablebase.max_pieces() {
+            if let Some((wdl, _)) = tablebase.probe_wdl(&board) {
+                let eval_index = i - game.opening_moves as usize;
+                let sign = if board.side_to_move() == Color::White { 1 } else { -1 };
+                let search_score = game.evals[eval_index].as_cp().map(|cp| cp * sign);
+                let score = match (wdl, config.soft_win_score, search_score) {
+                    (Wdl::Loss, Some(soft), Some(cp)) => cp.min(-soft),
+                    (Wdl::Win, Some(soft), Some(cp)) => cp.max(soft),
+                    (Wdl::Loss, _, _) => -config.win_score,
+                    (Wdl::BlessedLoss, _, _) => -cursed_win_score(config, tablebase, &board),
+                    (Wdl::Draw, _, _) => 0,
+                    (Wdl::CursedWin, _, _) => cursed_win_score(config, tablebase, &board),
+                    (Wdl::Win, _, _) => config.win_score,
+                };
+                game.evals[eval_index] = Eval::cp(score * sign);
+                game.tablebase_evals[eval_index] = true;
+            }
+        }
+        board.play_unchecked(game.moves[i]);
+    }
+}
 
+pub fn run_apply_syzygy(config: &ApplySyzygyConfig, abort: &Arc<AtomicBool>) {
     let mut tablebase = Tablebase::new();
     tablebase.add_directory(&config.syzygy_directory).expect("Failed to add syzygy tablebases");
 
@@ -50,28 +116,54 @@ pub fn run_apply_syzygy(config: &ApplySyzygyConfig, abort: &Arc<AtomicBool>) {
     let mut in_file = BufReader::new(in_file);
     let mut out_file = BufWriter::new(out_file);
 
-    while let Some(mut game) = read_analyzed_game(&mut in_file).unwrap() {
-        let mut board = init_pos.clone();
-        for i in 0..game.moves.len() {
-            if i > game.opening_moves as usize && board.occupied().len() <= tablebase.max_pieces() {
-                if let Some((wdl, _)) = tablebase.probe_wdl(&board) {
-                    let score = match wdl {
-                        Wdl::Loss => -config.win_score,
-                        Wdl::BlessedLoss => -config.cursed_win_score,
-                        Wdl::Draw => 0,
-                        Wdl::CursedWin => config.cursed_win_score,
-                        Wdl::Win => config.win_score,
-                    };
-                    let sign = if board.side_to_move() == Color::White { 1 } else { -1 };
-                    game.evals[i - game.opening_moves as usize] = Eval::cp(score * sign);
+    // The reader feeds numbered games to the workers, which pass them on to the writer.
+    let (game_sink, games) = sync_channel::<(u64, AnalyzedGame)>(config.threads as usize * 4);
+    let (result_sink, results) = sync_channel::<(u64, AnalyzedGame)>(config.threads as usize * 4);
+    let games = Mutex::new(games);
+    let tablebase = &tablebase;
+    std::thread::scope(|scope| {
+        for _ in 0..config.threads {
+            let games = &games;
+            let result_sink = result_sink.clone();
+            scope.spawn(move || loop {
+                let next = games.lock().unwrap().recv();
+                let (index, mut game) = match next {
+                    Ok(next) => next,
+                    Err(_) => break
+                };
+                apply_syzygy(config, tablebase, &mut game);
+                result_sink.send((index, game)).unwrap();
+            });
+        }
+        drop(result_sink);
+
+        scope.spawn(move || {
+            let mut pending = BTreeMap::new();
+            let mut next_index = 0;
+            for (index, game) in results {
+                if !config.preserve_order {
+                    write_analyzed_game(&game, &mut out_file).unwrap();
+                } else {
+                    pending.insert(index, game);
+                    while let Some(game) = pending.remove(&next_index) {
+                        write_analyzed_game(&game, &mut out_file).unwrap();
+                        next_index += 1;
+                    }
                 }
+                // Flush every game so the output is always made of complete games.
+                out_file.flush().unwrap();
             }
-            board.play_unchecked(game.moves[i]);
-        }
-        write_analyzed_game(&game, &mut out_file).unwrap();
+        });
+
+        let mut index = 0;
+        while let Some(game) = read_analyzed_game(&mut in_file).unwrap() {
+            game_sink.send((index, game)).unwrap();
+            index += 1;
 
-        if abort.load(Ordering::SeqCst) {
-            break;
+            if abort.load(Ordering::SeqCst) {
+                break;
+            }
         }
-    }
+        drop(game_sink);
+    });
 }
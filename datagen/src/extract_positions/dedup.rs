@@ -0,0 +1,54 @@
+use std::collections::HashSet;
+
+/// A set of position hashes used to drop repeated positions.
+pub enum PositionSet {
+    Exact(HashSet<u64>),
+    Bloom(BloomFilter)
+}
+
+impl PositionSet {
+    /// Insert a hash, returning whether it was (probably) new.
+    pub fn insert(&mut self, hash: u64) -> bool {
+        match self {
+            Self::Exact(set) => set.insert(hash),
+            Self::Bloom(filter) => filter.insert(hash)
+        }
+    }
+}
+
+/// A bloom filter over position hashes.
+/// Uses double hashing to derive the probe indices from a single zobrist hash.
+pub struct BloomFilter {
+    bits: Vec<u64>,
+    hashes: u32
+}
+
+impl BloomFilter {
+    pub fn new(capacity: u64, false_positive_rate: f64) -> Self {
+        let ln2 = std::f64::consts::LN_2;
+        let bit_count = (-(capacity.max(1) as f64) * false_positive_rate.ln() / (ln2 * ln2)).ceil() as u64;
+        let hashes = ((bit_count as f64 / capacity.max(1) as f64) * ln2).round().max(1.0) as u32;
+        Self {
+            bits: vec![0; bit_count.max(64).div_ceil(64) as usize],
+            hashes
+        }
+    }
+
+    fn bit_count(&self) -> u64 {
+        self.bits.len() as u64 * 64
+    }
+
+    pub fn insert(&mut self, hash: u64) -> bool {
+        let h1 = hash;
+        let h2 = hash.rotate_left(32).wrapping_mul(0x9E3779B97F4A7C15) | 1;
+        let mut new = false;
+        for i in 0..self.hashes as u64 {
+            let bit = h1.wrapping_add(i.wrapping_mul(h2)) % self.bit_count();
+            let word = &mut self.bits[(bit / 64) as usize];
+            let mask = 1 << (bit % 64);
+            new |= *word & mask == 0;
+            *word |= mask;
+        }
+        new
+    }
+}
@@ -1,4 +1,4 @@
-use std::io::Write;
+use std::io::{Read, Write};
 
 use cozy_chess::*;
 
@@ -10,17 +10,23 @@ fn square_index(bitboard: BitBoard, square: Square) -> usize {
     (bitboard & squares_behind).len() as usize
 }
 
-pub fn write_as_marlinformat(out: &mut impl Write, board: &Board, cp: i16, winner: Option<Color>) -> std::io::Result<()> {
+/// Write a position in MarlinFormat.
+/// `marks` goes in the otherwise unused extra byte at the end of the record.
+pub fn write_as_marlinformat(out: &mut impl Write, board: &Board, cp: i16, winner: Option<Color>, marks: u8) -> std::io::Result<()> {
     out.write_all(&board.occupied().0.to_le_bytes())?;
 
+    // Castling rights are stored as unmoved rooks on the rook's actual square,
+    // which also covers Chess960 rook files. Both sides keep their rights.
     let mut unmoved_rooks = BitBoard::EMPTY;
-    let castling_rights = board.castle_rights(board.side_to_move());
-    let back_rank = Rank::First.relative_to(board.side_to_move());
-    if let Some(file) = castling_rights.short {
-        unmoved_rooks |= Square::new(file, back_rank).bitboard();
-    }
-    if let Some(file) = castling_rights.long {
-        unmoved_rooks |= Square::new(file, back_rank).bitboard();
+    for &color in &Color::ALL {
+        let castling_rights = board.castle_rights(color);
+        let back_rank = Rank::First.relative_to(color);
+        if let Some(file) = castling_rights.short {
+            unmoved_rooks |= Square::new(file, back_rank).bitboard();
+        }
+        if let Some(file) = castling_rights.long {
+            unmoved_rooks |= Square::new(file, back_rank).bitboard();
+        }
     }
     let mut encoded_pieces = [0; 32];
     for &color in &Color::ALL {
@@ -56,7 +62,164 @@ pub fn write_as_marlinformat(out: &mut impl Write, board: &Board, cp: i16, winne
     };
     out.write_all(&cp.to_le_bytes())?;
     out.write_all(&[wdl])?;
-    out.write_all(&[0])?;
+    out.write_all(&[marks])?;
 
     Ok(())
 }
+
+pub struct MarlinFormatRecord {
+    pub board: Board,
+    pub cp: i16,
+    pub wdl: u8,
+    pub marks: u8
+}
+
+fn invalid_data(message: &str) -> std::io::Error {
+    std::io::Error::new(std::io::ErrorKind::InvalidData, message)
+}
+
+/// Read a position written by [`write_as_marlinformat`].
+/// Returns `None` at the end of the input.
+pub fn read_marlinformat(reader: &mut impl Read) -> std::io::Result<Option<MarlinFormatRecord>> {
+    let mut record = [0; 32];
+    match reader.read_exact(&mut record) {
+        Ok(()) => {}
+        Err(e) if e.kind() == std::io::ErrorKind::UnexpectedEof => return Ok(None),
+        Err(e) => return Err(e)
+    }
+    let occupied = BitBoard(u64::from_le_bytes(record[0..8].try_into().unwrap()));
+    if occupied.len() > 32 {
+        return Err(invalid_data("Too many pieces"));
+    }
+
+    let mut squares = [None; Square::NUM];
+    let mut unmoved_rooks = [Vec::new(), Vec::new()];
+    for (index, square) in occupied.into_iter().enumerate() {
+        let encoded = (record[8 + index / 2] >> (index % 2 * 4)) & 0b1111;
+        let color = Color::index((encoded >> 3) as usize);
+        let piece = match encoded & 0b111 {
+            UNMOVED_ROOK => {
+                unmoved_rooks[color as usize].push(square.file());
+                Piece::Rook
+            }
+            piece if (piece as usize) < Piece::NUM => Piece::index(piece as usize),
+            _ => return Err(invalid_data("Invalid piece"))
+        };
+        squares[square as usize] = Some((piece, color));
+    }
+
+    let mut fen = String::new();
+    for &rank in Rank::ALL.iter().rev() {
+        let mut empty = 0;
+        for &file in &File::ALL {
+            match squares[Square::new(file, rank) as usize] {
+                Some((piece, color)) => {
+                    if empty > 0 {
+                        fen.push_str(&empty.to_string());
+                        empty = 0;
+                    }
+                    let c = match piece {
+                        Piece::Pawn => 'p',
+                        Piece::Knight => 'n',
+                        Piece::Bishop => 'b',
+                        Piece::Rook => 'r',
+                        Piece::Queen => 'q',
+                        Piece::King => 'k'
+                    };
+                    fen.push(if color == Color::White { c.to_ascii_uppercase() } else { c });
+                }
+                None => empty += 1
+            }
+        }
+        if empty > 0 {
+            fen.push_str(&empty.to_string());
+        }
+        if rank != Rank::First {
+            fen.push('/');
+        }
+    }
+
+    let side_to_move = Color::index((record[24] >> 7) as usize);
+    fen.push_str(if side_to_move == Color::White { " w " } else { " b " });
+
+    let mut castling = String::new();
+    for &color in &Color::ALL {
+        for &file in unmoved_rooks[color as usize].iter().rev() {
+            let c = char::from(file);
+            castling.push(if color == Color::White { c.to_ascii_uppercase() } else { c });
+        }
+    }
+    if castling.is_empty() {
+        castling.push('-');
+    }
+    fen.push_str(&castling);
+
+    let ep_square = record[24] & 0b1111111;
+    if ep_square == NO_SQUARE {
+        fen.push_str(" -");
+    } else if (ep_square as usize) < Square::NUM {
+        fen.push(' ');
+        fen.push_str(&Square::index(ep_square as usize).to_string());
+    } else {
+        return Err(invalid_data("Invalid en passant square"));
+    }
+
+    let halfmove_clock = record[25];
+    let fullmove_number = u16::from_le_bytes([record[26], record[27]]);
+    fen.push_str(&format!(" {} {}", halfmove_clock, fullmove_number));
+
+    let board = Board::from_fen(&fen, true).map_err(|_| invalid_data("Invalid position"))?;
+    Ok(Some(MarlinFormatRecord {
+        board,
+        cp: i16::from_le_bytes([record[28], record[29]]),
+        wdl: record[30],
+        marks: record[31]
+    }))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn round_trip(board: &Board) -> Board {
+        let mut encoded = Vec::new();
+        write_as_marlinformat(&mut encoded, board, -123, Some(Color::Black), 5).unwrap();
+        assert_eq!(encoded.len(), 32);
+        let record = read_marlinformat(&mut encoded.as_slice()).unwrap().unwrap();
+        assert_eq!(record.cp, -123);
+        assert_eq!(record.wdl, 0);
+        assert_eq!(record.marks, 5);
+        assert!(read_marlinformat(&mut &encoded[32..]).unwrap().is_none());
+        record.board
+    }
+
+    // A Chess960 start with the rooks on the b and f files.
+    const CHESS960: &str = "nrbkqrbn/pppppppp/8/8/8/8/PPPPPPPP/NRBKQRBN w FBfb - 0 1";
+
+    #[test]
+    fn chess960_castling_kept() {
+        let board = Board::from_fen(CHESS960, true).unwrap();
+        let decoded = round_trip(&board);
+        assert_eq!(format!("{:#}", decoded), format!("{:#}", board));
+        for &color in &Color::ALL {
+            let rights = decoded.castle_rights(color);
+            assert_eq!(rights.short, Some(File::F));
+            assert_eq!(rights.long, Some(File::B));
+        }
+    }
+
+    #[test]
+    fn chess960_castling_lost() {
+        let mut board = Board::from_fen(CHESS960, true).unwrap();
+        // White moves its f rook and black its b rook, so each keeps only one right.
+        for mv in ["f2f4", "b7b6", "f1f2", "b8b7"] {
+            board.play(mv.parse().unwrap());
+        }
+        let decoded = round_trip(&board);
+        assert_eq!(format!("{:#}", decoded), format!("{:#}", board));
+        let white = decoded.castle_rights(Color::White);
+        assert_eq!((white.short, white.long), (None, Some(File::B)));
+        let black = decoded.castle_rights(Color::Black);
+        assert_eq!((black.short, black.long), (Some(File::F), None));
+    }
+}
@@ -12,8 +12,16 @@ use cozy_chess::*;
 use crate::analyzed_game::read_analyzed_game;
 
 use marlinformat::write_as_marlinformat;
+use dedup::{PositionSet, BloomFilter};
 
 mod marlinformat;
+mod dedup;
+mod verify;
+
+pub use verify::{VerifyFormatConfig, run_verify_format};
+
+const CAPTURE_MARK: u8 = 0b01;
+const CHECKERS_MARK: u8 = 0b10;
 
 #[derive(Debug, Clone, Copy, ValueEnum)]
 enum PositionFormat {
@@ -22,6 +30,13 @@ enum PositionFormat {
     DryRun
 }
 
+#[derive(Debug, Clone, Copy, ValueEnum)]
+enum DedupMode {
+    None,
+    Exact,
+    Bloom
+}
+
 #[derive(Debug, Args)]
 /// Extract positions from analyzed games
 pub struct ExtractPositionsConfig {
@@ -49,13 +64,99 @@ pub struct ExtractPositionsConfig {
     #[clap(long, default_value_t = true)]
     exclude_checkers: bool,
 
-    /// Max absolute eval to be included
+    /// Keep positions reached from captures or with checkers, but mark them.
+    /// The marks are a bitset where bit 0 is set for captures and bit 1 is set for checkers.
+    /// They're stored in the extra byte of MarlinFormat and as a fourth field of FenCpWdl.
+    #[clap(long)]
+    mark_captures: bool,
+
+    /// Seed for the position sampling
+    #[clap(long, default_value_t = 0xcafef00dd15ea5e5)]
+    seed: u64,
+
+    /// Max absolute eval to be included.
+    /// Tablebase evals from ApplySyzygy's default win score are exactly this, so they're kept.
     #[clap(long, default_value_t = 20_000)]
-    max_eval: i16
+    max_eval: i16,
+
+    /// Exclude positions whose eval came from a tablebase
+    #[clap(long)]
+    exclude_tablebase: bool,
+
+    /// Exclude positions where the eval favours the side that lost by at least this much.
+    /// Drawn games aren't filtered.
+    #[clap(long)]
+    consistency: Option<i16>,
+
+    /// Relative keep rates for evenly sized game phase buckets, from endgame to opening, like `4,2,1`.
+    /// Positions are subsampled by these before --max-samples is applied.
+    #[clap(long, value_parser = parse_phase_balance)]
+    phase_balance: Option<PhaseBalance>,
+
+    /// Drop positions already written, by zobrist hash.
+    /// The bloom filter uses bounded memory but may drop some unique positions.
+    #[clap(long, value_enum, value_parser, default_value_t = DedupMode::None)]
+    dedup: DedupMode,
+
+    /// Expected number of positions for the bloom filter
+    #[clap(long, default_value_t = 100_000_000)]
+    bloom_capacity: u64,
+
+    /// Target false positive rate for the bloom filter
+    #[clap(long, default_value_t = 0.001)]
+    bloom_false_positive_rate: f64
+}
+
+const MAX_PHASE: u32 = 24;
+
+/// The keep rates for --phase-balance, one per bucket.
+#[derive(Debug, Clone)]
+struct PhaseBalance(Vec<f32>);
+
+/// Parse --phase-balance, rejecting weights that can't be used for sampling.
+fn parse_phase_balance(weights: &str) -> Result<PhaseBalance, String> {
+    let weights = weights.split(',')
+        .map(|weight| weight.trim().parse::<f32>().map_err(|_| format!("Invalid weight {:?}", weight)))
+        .collect::<Result<Vec<_>, _>>()?;
+    if weights.len() > MAX_PHASE as usize + 1 {
+        return Err(format!("Expected at most {} weights, one per phase", MAX_PHASE + 1));
+    }
+    if weights.iter().any(|weight| !weight.is_finite() || *weight < 0.0) {
+        return Err("Weights must be finite and non-negative".to_owned());
+    }
+    if !weights.iter().any(|&weight| weight > 0.0) {
+        return Err("At least one weight must be positive".to_owned());
+    }
+    Ok(PhaseBalance(weights))
+}
+
+/// The game phase from the non-pawn material, where 0 is a pawn endgame and `MAX_PHASE` is the start position.
+fn game_phase(board: &Board) -> u32 {
+    let phase = board.pieces(Piece::Knight).len()
+        + board.pieces(Piece::Bishop).len()
+        + board.pieces(Piece::Rook).len() * 2
+        + board.pieces(Piece::Queen).len() * 4;
+    phase.min(MAX_PHASE)
+}
+
+/// Format the FEN, using Shredder castling rights if standard ones would be ambiguous.
+fn fen(board: &Board) -> String {
+    let standard_castling = Color::ALL.iter().all(|&color| {
+        let rights = board.castle_rights(color);
+        let back_rank = Rank::First.relative_to(color);
+        let king_on_e = board.king(color) == Square::new(cozy_chess::File::E, back_rank);
+        ((rights.short.is_none() && rights.long.is_none()) || king_on_e)
+            && rights.short.map_or(true, |file| file == cozy_chess::File::H)
+            && rights.long.map_or(true, |file| file == cozy_chess::File::A)
+    });
+    if standard_castling {
+        format!("{}", board)
+    } else {
+        format!("{:#}", board)
+    }
 }
 
 pub fn run_position_extraction(config: &ExtractPositionsConfig, abort: &Arc<AtomicBool>) {
-    let init_pos = Board::default();
     let in_file = File::open(&config.in_file).expect("Failed to open in file");
     let out_file = File::options()
         .write(true)
@@ -64,13 +165,23 @@ pub fn run_position_extraction(config: &ExtractPositionsConfig, abort: &Arc<Atom
         .expect("Failed to create out file");
     let mut in_file = BufReader::new(in_file);
     let mut out_file = BufWriter::new(out_file);
-    let mut rng = Pcg64Mcg::new(0xcafef00dd15ea5e5);
+    let mut rng = Pcg64Mcg::new(config.seed as u128);
     let mut games = 0u64;
     let mut positions = 0u64;
+    let mut duplicates = 0u64;
+    let mut inconsistent_positions = 0u64;
+    let mut seen = match config.dedup {
+        DedupMode::None => None,
+        DedupMode::Exact => Some(PositionSet::Exact(Default::default())),
+        DedupMode::Bloom => Some(PositionSet::Bloom(BloomFilter::new(
+            config.bloom_capacity,
+            config.bloom_false_positive_rate
+        )))
+    };
     while let Some(game) = read_analyzed_game(&mut in_file).unwrap() {
         let mut samples = Vec::new();
         // TODO better name
-        let mut next_board = init_pos.clone();
+        let mut next_board = game.start_pos.clone();
         for (i, &mv) in game.moves.iter().enumerate() {
             let board = next_board.clone();
             next_board.play_unchecked(mv);
@@ -78,26 +189,60 @@ pub fn run_position_extraction(config: &ExtractPositionsConfig, abort: &Arc<Atom
             if i < game.opening_moves as usize {
                 continue;
             }
-            let cp = match game.evals[i - game.opening_moves as usize].as_cp() {
+            let eval_index = i - game.opening_moves as usize;
+            if config.exclude_tablebase && game.tablebase_evals[eval_index] {
+                continue;
+            }
+            let cp = match game.evals[eval_index].as_cp() {
                 Some(cp) => cp,
                 None => continue,
             };
 
+            let mut marks = 0;
             let is_capture = next_board.occupied().len() < board.occupied().len();
-            if config.exclude_captures && is_capture {
-                continue;
+            if is_capture {
+                if config.exclude_captures && !config.mark_captures {
+                    continue;
+                }
+                marks |= CAPTURE_MARK;
             }
 
             let has_checkers = !board.checkers().is_empty() || !next_board.checkers().is_empty();
-            if config.exclude_checkers && has_checkers {
-                continue;
+            if has_checkers {
+                if config.exclude_checkers && !config.mark_captures {
+                    continue;
+                }
+                marks |= CHECKERS_MARK;
             }
 
             if cp.abs() > config.max_eval {
                 continue;
             }
 
-            samples.push((board, cp));
+            if let Some(threshold) = config.consistency {
+                let inconsistent = match game.winner {
+                    Some(Color::White) => cp <= -threshold,
+                    Some(Color::Black) => cp >= threshold,
+                    None => false
+                };
+                if inconsistent {
+                    inconsistent_positions += 1;
+                    continue;
+                }
+            }
+
+            if !config.mark_captures {
+                marks = 0;
+            }
+            if let Some(PhaseBalance(weights)) = &config.phase_balance {
+                let bucket = game_phase(&board) as usize * weights.len() / (MAX_PHASE as usize + 1);
+                let max_weight = weights.iter().copied().fold(0.0, f32::max);
+                if rng.gen::<f32>() * max_weight >= weights[bucket] {
+                    continue;
+                }
+            }
+
+            samples.push((board, cp, marks));
         }
 
         // Partially shuffle into starting elements
@@ -106,25 +251,37 @@ pub fn run_position_extraction(config: &ExtractPositionsConfig, abort: &Arc<Atom
             samples.swap(i, random);
         }
         samples.truncate(config.max_samples as usize);
+        if let Some(seen) = &mut seen {
+            let sampled = samples.len();
+            samples.retain(|(board, _, _)| seen.insert(board.hash()));
+            duplicates += (sampled - samples.len()) as u64;
+        }
 
         match config.format {
             PositionFormat::MarlinFormat => {
-                for (board, cp) in &samples {
-                    write_as_marlinformat(&mut out_file, board, *cp, game.winner).unwrap();
+                for (board, cp, marks) in &samples {
+                    write_as_marlinformat(&mut out_file, board, *cp, game.winner, *marks).unwrap();
                 }
             }
             PositionFormat::FenCpWdl => {
-                for (board, cp) in &samples {
+                for (board, cp, marks) in &samples {
                     let wdl = match game.winner {
                         Some(Color::White) => "1.0",
                         Some(Color::Black) => "0.0",
                         None => "0.5",
                     };
-                    writeln!(&mut out_file, "{} | {} | {}", &board, cp, wdl).unwrap();
+                    let fen = fen(board);
+                    if config.mark_captures {
+                        writeln!(&mut out_file, "{} | {} | {} | {}", fen, cp, wdl, marks).unwrap();
+                    } else {
+                        writeln!(&mut out_file, "{} | {} | {}", fen, cp, wdl).unwrap();
+                    }
                 }
             }
             PositionFormat::DryRun => {}
         }
+        // Flush every game so the output is always made of complete games.
+        out_file.flush().unwrap();
         positions += samples.len() as u64;
         games += 1;
 
@@ -133,4 +290,10 @@ pub fn run_position_extraction(config: &ExtractPositionsConfig, abort: &Arc<Atom
         }
     }
     eprintln!("Wrote {} positions extracted from {} games.", positions, games);
+    if config.consistency.is_some() {
+        eprintln!("Dropped {} positions inconsistent with the game result.", inconsistent_positions);
+    }
+    if seen.is_some() {
+        eprintln!("Dropped {} duplicate positions.", duplicates);
+    }
 }
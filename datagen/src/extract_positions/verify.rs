@@ -0,0 +1,54 @@
+use std::fs::File;
+use std::io::BufReader;
+use std::path::PathBuf;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
+
+use clap::Args;
+
+use crate::analyzed_game::read_analyzed_game;
+use super::marlinformat::{read_marlinformat, write_as_marlinformat};
+
+#[derive(Debug, Args)]
+/// Check that every position in a set of games round trips through MarlinFormat
+pub struct VerifyFormatConfig {
+    /// The input file of analyzed games
+    #[clap(short, long)]
+    in_file: PathBuf
+}
+
+pub fn run_verify_format(config: &VerifyFormatConfig, abort: &Arc<AtomicBool>) {
+    let in_file = File::open(&config.in_file).expect("Failed to open in file");
+    let mut in_file = BufReader::new(in_file);
+    let mut positions = 0u64;
+    let mut failures = 0u64;
+    let mut record = Vec::new();
+    while let Some(game) = read_analyzed_game(&mut in_file).unwrap() {
+        let mut board = game.start_pos.clone();
+        for &mv in &game.moves {
+            record.clear();
+            write_as_marlinformat(&mut record, &board, 0, game.winner, 0).unwrap();
+            let decoded = read_marlinformat(&mut record.as_slice());
+            let expected = format!("{:#}", board);
+            match decoded {
+                Ok(Some(decoded)) if format!("{:#}", decoded.board) == expected => {}
+                Ok(Some(decoded)) => {
+                    failures += 1;
+                    eprintln!("Mismatch: {} decoded as {:#}", expected, decoded.board);
+                }
+                Ok(None) => unreachable!(),
+                Err(error) => {
+                    failures += 1;
+                    eprintln!("Failed to decode {}: {}", expected, error);
+                }
+            }
+            positions += 1;
+            board.play_unchecked(mv);
+        }
+
+        if abort.load(Ordering::SeqCst) {
+            break;
+        }
+    }
+    eprintln!("Verified {} positions with {} failures.", positions, failures);
+}
@@ -0,0 +1,66 @@
+use cozy_chess::*;
+
+/// The knight placements among the five files left after the bishops and queen, by Scharnagl number.
+const KNIGHT_PLACEMENTS: [(usize, usize); 10] = [
+    (0, 1), (0, 2), (0, 3), (0, 4), (1, 2),
+    (1, 3), (1, 4), (2, 3), (2, 4), (3, 4)
+];
+
+/// The Chess960 start position with the given Scharnagl number in `0..960`.
+// CITE: Scharnagl numbering.
+// https://en.wikipedia.org/wiki/Fischer_random_chess_numbering_scheme
+pub fn chess960_start_pos(mut index: usize) -> Board {
+    let mut back_rank = [None; 8];
+    back_rank[index % 4 * 2 + 1] = Some(Piece::Bishop);
+    index /= 4;
+    back_rank[index % 4 * 2] = Some(Piece::Bishop);
+    index /= 4;
+
+    fn place_nth_empty(back_rank: &mut [Option<Piece>; 8], n: usize, piece: Piece) {
+        let file = back_rank.iter()
+            .enumerate()
+            .filter(|(_, p)| p.is_none())
+            .nth(n)
+            .unwrap()
+            .0;
+        back_rank[file] = Some(piece);
+    }
+    place_nth_empty(&mut back_rank, index % 6, Piece::Queen);
+    index /= 6;
+    let (first_knight, second_knight) = KNIGHT_PLACEMENTS[index];
+    // Place the second knight first so the first knight's index isn't shifted.
+    place_nth_empty(&mut back_rank, second_knight, Piece::Knight);
+    place_nth_empty(&mut back_rank, first_knight, Piece::Knight);
+    place_nth_empty(&mut back_rank, 0, Piece::Rook);
+    place_nth_empty(&mut back_rank, 0, Piece::King);
+    place_nth_empty(&mut back_rank, 0, Piece::Rook);
+
+    let mut white_rank = String::new();
+    let mut rook_files = Vec::new();
+    for (file, piece) in back_rank.iter().enumerate() {
+        let piece = piece.unwrap();
+        if piece == Piece::Rook {
+            rook_files.push(char::from(File::index(file)));
+        }
+        white_rank.push(match piece {
+            Piece::Pawn => 'P',
+            Piece::Knight => 'N',
+            Piece::Bishop => 'B',
+            Piece::Rook => 'R',
+            Piece::Queen => 'Q',
+            Piece::King => 'K'
+        });
+    }
+    let black_rank = white_rank.to_ascii_lowercase();
+    let (long, short) = (rook_files[0], rook_files[1]);
+    let fen = format!(
+        "{}/pppppppp/8/8/8/8/PPPPPPPP/{} w {}{}{}{} - 0 1",
+        black_rank,
+        white_rank,
+        short.to_ascii_uppercase(),
+        long.to_ascii_uppercase(),
+        short,
+        long
+    );
+    Board::from_fen(&fen, true).unwrap()
+}
@@ -1,4 +1,5 @@
 use cozy_chess::*;
+use tantabus::search::count_repetitions;
 
 pub struct ChessGame {
     board: Board,
@@ -7,8 +8,7 @@ pub struct ChessGame {
 }
 
 impl ChessGame {
-    pub fn new() -> Self {
-        let board = Board::default();
+    pub fn new(board: Board) -> Self {
         let history = vec![board.hash()];
         let moves = Vec::new();
         Self { board, history, moves }
@@ -37,13 +37,9 @@ impl ChessGame {
         if status != GameStatus::Ongoing {
             return status;
         }
-        let repetitions = self.history.iter()
-            .rev()
-            .take(self.board.halfmove_clock() as usize + 1)
-            .step_by(2) // Every second ply so it's our turn
-            .filter(|&&hash| hash == self.board.hash())
-            .count();
-        if repetitions >= 3 {
+        // The last entry is our board.
+        let (_, history) = self.history.split_last().unwrap();
+        if count_repetitions(history, &self.board) >= 2 {
             return GameStatus::Drawn;
         }
         GameStatus::Ongoing
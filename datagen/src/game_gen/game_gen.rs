@@ -1,21 +1,25 @@
+use std::sync::Arc;
+
 use cozy_chess::*;
 
 use rand::prelude::*;
+use tantabus::eval::{Eval, EvalKind};
 use tantabus::search::*;
 
 use crate::analyzed_game::AnalyzedGame;
 use super::chess_game::ChessGame;
+use super::chess960::chess960_start_pos;
 
-fn random_opening(opening_moves: u8) -> ChessGame {
-    fn try_random_opening(opening_moves: u8) -> Option<ChessGame> {
-        let mut game = ChessGame::new();
+fn random_opening(rng: &mut impl Rng, start_pos: &Board, opening_moves: u8) -> ChessGame {
+    fn try_random_opening(rng: &mut impl Rng, start_pos: &Board, opening_moves: u8) -> Option<ChessGame> {
+        let mut game = ChessGame::new(start_pos.clone());
         for _ in 0..opening_moves {
             let mut moves = Vec::new();
             game.board().generate_moves(|move_set| {
                 moves.extend(move_set);
                 false
             });
-            let mv = *moves.choose(&mut thread_rng()).unwrap();
+            let mv = *moves.choose(rng).unwrap();
             game.play_unchecked(mv);
             if game.game_status() != GameStatus::Ongoing {
                 return None;
@@ -24,7 +28,7 @@ fn random_opening(opening_moves: u8) -> ChessGame {
         Some(game)
     }
     loop {
-        if let Some(game) = try_random_opening(opening_moves) {
+        if let Some(game) = try_random_opening(rng, start_pos, opening_moves) {
             return game;
         }
     }
@@ -39,31 +43,105 @@ struct Handler {
 
 impl SearchHandler for Handler {
     fn stop_search(&self, _nodes: u64) -> bool {
-        if let Some(result) = &self.prev_result {
-            result.depth >= self.min_depth && result.nodes >= self.min_nodes
-        } else {
-            false
-        }
+        // Normally `continue_deepening` ends the search first, but this keeps
+        // an iteration that's already started from running to completion.
+        self.prev_result.as_ref().map_or(false, |result| !self.continue_deepening(result))
     }
 
     fn new_result(&mut self, search_result: SearchResult) {
         self.nodes += search_result.nodes;
         self.prev_result = Some(search_result);
     }
+
+    fn continue_deepening(&self, last: &SearchResult) -> bool {
+        last.depth < self.min_depth || last.nodes < self.min_nodes
+    }
 }
 
 #[derive(Debug, Clone)]
 pub struct GameGenConfig {
     pub cache_size: usize,
+    pub openings: Option<Arc<Vec<Board>>>,
+    pub frc: bool,
     pub opening_moves: u8,
     pub min_nodes: u64,
-    pub min_depth: u8
+    pub min_depth: u8,
+    pub resign_threshold: i16,
+    pub resign_plies: u32,
+    pub draw_threshold: i16,
+    pub draw_plies: u32,
+    pub draw_min_moves: u32,
+    pub draw_max_material: u32
+}
+
+/// The non-pawn material on the board for both sides, in pawns.
+fn non_pawn_material(board: &Board) -> u32 {
+    [(Piece::Knight, 3), (Piece::Bishop, 3), (Piece::Rook, 5), (Piece::Queen, 9)]
+        .iter()
+        .map(|&(piece, value)| board.pieces(piece).len() as u32 * value)
+        .sum()
 }
 
-pub fn gen_game(config: &GameGenConfig) -> AnalyzedGame {
-    let init_pos = Board::default();
-    let mut game = random_opening(config.opening_moves);
+/// Tracks how long the evals have been decisive or drawish for adjudication.
+#[derive(Default)]
+struct Adjudicator {
+    winning_side: Option<Color>,
+    winning_plies: u32,
+    drawn_plies: u32
+}
+
+impl Adjudicator {
+    /// Update with the next eval from white's perspective.
+    fn update(&mut self, config: &GameGenConfig, eval: Eval) {
+        let (winning, drawn) = match eval.kind() {
+            EvalKind::Centipawn(cp) => (cp.abs() >= config.resign_threshold, cp.abs() <= config.draw_threshold),
+            _ => (true, false)
+        };
+        if winning {
+            let side = if eval > Eval::ZERO { Color::White } else { Color::Black };
+            if self.winning_side != Some(side) {
+                self.winning_side = Some(side);
+                self.winning_plies = 0;
+            }
+            self.winning_plies += 1;
+        } else {
+            self.winning_side = None;
+            self.winning_plies = 0;
+        }
+        if drawn {
+            self.drawn_plies += 1;
+        } else {
+            self.drawn_plies = 0;
+        }
+    }
+
+    /// The adjudicated result, if any. `Some(None)` is a draw.
+    /// Draws are only adjudicated once the game is long enough and material has come off,
+    /// so drawish middlegames still get played out.
+    fn result(&self, config: &GameGenConfig, game: &ChessGame) -> Option<Option<Color>> {
+        if config.resign_plies > 0 && self.winning_plies >= config.resign_plies {
+            return Some(self.winning_side);
+        }
+        if config.draw_plies > 0
+            && self.drawn_plies >= config.draw_plies
+            && game.moves().len() as u32 >= config.draw_min_moves * 2
+            && non_pawn_material(game.board()) <= config.draw_max_material
+        {
+            return Some(None);
+        }
+        None
+    }
+}
+
+pub fn gen_game(config: &GameGenConfig, rng: &mut impl Rng) -> AnalyzedGame {
+    let init_pos = match &config.openings {
+        Some(openings) => openings.choose(rng).unwrap().clone(),
+        None if config.frc => chess960_start_pos(rng.gen_range(0..960)),
+        None => Board::default()
+    };
+    let mut game = random_opening(rng, &init_pos, config.opening_moves);
     let mut evals = Vec::new();
+    let mut adjudicator = Adjudicator::default();
     let mut cache_table = CacheTable::new_with_size(config.cache_size).unwrap();
     loop {
         let mut handler = Handler {
@@ -77,29 +155,33 @@ pub fn gen_game(config: &GameGenConfig) -> AnalyzedGame {
             init_pos.clone(),
             game.moves().iter().copied(),
             EngineOptions::default(),
-            SearchParams::default(),
+            SearchParams::datagen(),
             cache_table
         );
         engine.search();
         cache_table = engine.into_cache_table();
 
         let analysis = handler.prev_result.unwrap();
-        evals.push(match game.board().side_to_move() {
+        let eval = match game.board().side_to_move() {
             Color::White => analysis.eval,
             Color::Black => -analysis.eval
-        });
-        game.play_unchecked(analysis.mv);
+        };
+        evals.push(eval);
+        adjudicator.update(config, eval);
+        game.play_unchecked(analysis.mv.unwrap());
 
         let status = game.game_status();
-        if status != GameStatus::Ongoing {
-            let winner = if status == GameStatus::Won {
-                Some(!game.board().side_to_move())
-            } else {
-                None
-            };
+        let winner = match status {
+            GameStatus::Won => Some(Some(!game.board().side_to_move())),
+            GameStatus::Drawn => Some(None),
+            GameStatus::Ongoing => adjudicator.result(config, &game)
+        };
+        if let Some(winner) = winner {
             return AnalyzedGame {
+                start_pos: init_pos,
                 opening_moves: config.opening_moves,
                 moves: game.into_moves(),
+                tablebase_evals: vec![false; evals.len()],
                 evals,
                 winner
             };
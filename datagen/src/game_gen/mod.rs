@@ -1,22 +1,24 @@
 use std::fs::File;
-use std::io::BufWriter;
-use std::path::PathBuf;
-use std::sync::{Arc, Mutex};
+use std::io::{Write, BufRead, BufReader, BufWriter, ErrorKind, Seek, SeekFrom};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::sync::mpsc::sync_channel;
 use std::sync::atomic::{AtomicBool, Ordering};
 use std::time::Instant;
 
 use clap::Args;
+use rand::SeedableRng;
+use rand_pcg::Pcg64Mcg;
+use cozy_chess::Board;
 
 mod chess_game;
+mod chess960;
 mod game_gen;
 
 use game_gen::{GameGenConfig, gen_game};
 
-use crate::analyzed_game::write_analyzed_game;
-
-fn default_threads() -> u32 {
-    std::thread::available_parallelism().map_or(1, |t| t.get() as u32)
-}
+use crate::default_threads;
+use crate::analyzed_game::{read_analyzed_game, write_analyzed_game};
 
 const GAMES_PER_LOG: u64 = 100;
 
@@ -35,6 +37,15 @@ pub struct GameGenRunnerConfig {
     #[clap(long, default_value_t  = 1)]
     cache_size: u32,
 
+    /// An EPD or FEN file of start positions to sample from.
+    /// The randomized opening moves are played from these.
+    #[clap(long)]
+    openings: Option<PathBuf>,
+
+    /// Start games from random Chess960 positions. Ignored if an openings file is given.
+    #[clap(long)]
+    frc: bool,
+
     /// Number of randomized opening moves
     #[clap(long, default_value_t = 8)]
     opening_moves: u8,
@@ -45,60 +56,173 @@ pub struct GameGenRunnerConfig {
 
     /// Minimum depth per move
     #[clap(long, default_value_t = 7)]
-    min_depth: u8
+    min_depth: u8,
+
+    /// Absolute eval at which a game is adjudicated as a win
+    #[clap(long, default_value_t = 1000)]
+    resign_threshold: i16,
+
+    /// Consecutive plies at or above the resign threshold for a win adjudication. 0 disables it.
+    #[clap(long, default_value_t = 0)]
+    resign_plies: u32,
+
+    /// Absolute eval at which a game is considered drawish
+    #[clap(long, default_value_t = 10)]
+    draw_threshold: i16,
+
+    /// Consecutive plies at or below the draw threshold for a draw adjudication. 0 disables it.
+    #[clap(long, default_value_t = 0)]
+    draw_plies: u32,
+
+    /// Full moves played, including the opening, before a game can be adjudicated as a draw
+    #[clap(long, default_value_t = 40)]
+    draw_min_moves: u32,
+
+    /// Most non-pawn material, in pawns for both sides together, at which a game can be adjudicated as a draw
+    #[clap(long, default_value_t = 24)]
+    draw_max_material: u32,
+
+    /// Seed for the opening randomization. Each thread uses the seed plus its index.
+    /// Without one, every run is different.
+    /// Games are written as threads finish them, so only a single thread gives a reproducible file.
+    #[clap(long)]
+    seed: Option<u64>,
+
+    /// Append to an existing out file instead of creating a new one
+    #[clap(long)]
+    append: bool
+}
+
+/// Read start positions from an EPD or FEN file, one per line.
+/// Missing move counters default to `0 1` and any EPD operations are ignored.
+fn read_openings(path: &Path) -> Vec<Board> {
+    let file = BufReader::new(File::open(path).expect("Failed to open openings file"));
+    let mut openings = Vec::new();
+    for line in file.lines() {
+        let line = line.expect("Failed to read openings file");
+        let fields = line.split_whitespace().collect::<Vec<_>>();
+        if fields.is_empty() {
+            continue;
+        }
+        let mut fen = fields.iter().take(4).copied().collect::<Vec<_>>();
+        let counters = fields.get(4..6)
+            .filter(|c| c.iter().all(|field| field.parse::<u32>().is_ok()))
+            .unwrap_or(&["0", "1"]);
+        fen.extend_from_slice(counters);
+        let fen = fen.join(" ");
+        let board = Board::from_fen(&fen, false)
+            .or_else(|_| Board::from_fen(&fen, true))
+            .unwrap_or_else(|_| panic!("Invalid opening {}", line));
+        openings.push(board);
+    }
+    assert!(!openings.is_empty(), "Openings file is empty");
+    openings
 }
 
-struct GameGenSharedState {
-    out_file: BufWriter<File>,
-    last_log: Instant,
-    games_written: u64
+/// Open an existing out file for appending and count the games already in it.
+/// A partially written game at the end, as left by a crash, is truncated.
+/// Any other error means the file isn't what we expect, so it's left alone.
+fn open_for_append(path: &Path) -> std::io::Result<(File, u64)> {
+    let mut out_file = File::options()
+        .read(true)
+        .write(true)
+        .create(true)
+        .open(path)?;
+    let mut games = 0;
+    let mut complete_len = 0;
+    let mut reader = BufReader::new(&mut out_file);
+    loop {
+        match read_analyzed_game(&mut reader) {
+            Ok(Some(_)) => {
+                games += 1;
+                // Older games are shorter, so go by how much was actually read.
+                complete_len = reader.stream_position()?;
+            }
+            Ok(None) => break,
+            Err(error) if error.kind() == ErrorKind::UnexpectedEof => {
+                eprintln!("Truncating a partially written game after {} games.", games);
+                break;
+            }
+            Err(error) => return Err(error)
+        }
+    }
+    drop(reader);
+    out_file.set_len(complete_len)?;
+    out_file.seek(SeekFrom::End(0))?;
+    eprintln!("Appending to {} existing games.", games);
+    Ok((out_file, games))
 }
 
 pub fn run_game_gen(config: &GameGenRunnerConfig, abort: &Arc<AtomicBool>) {
     let threads = config.threads;
     let game_gen_config = GameGenConfig {
         cache_size: config.cache_size as usize * 1_000_000,
+        openings: config.openings.as_deref().map(|path| Arc::new(read_openings(path))),
+        frc: config.frc,
         opening_moves: config.opening_moves,
         min_nodes: config.min_nodes,
-        min_depth: config.min_depth
+        min_depth: config.min_depth,
+        resign_threshold: config.resign_threshold,
+        resign_plies: config.resign_plies,
+        draw_threshold: config.draw_threshold,
+        draw_plies: config.draw_plies,
+        draw_min_moves: config.draw_min_moves,
+        draw_max_material: config.draw_max_material
     };
-    let out_file = File::options()
-        .write(true)
-        .create_new(true)
-        .open(&config.out_file)
-        .expect("Failed to create out file");
-    let shared_state = GameGenSharedState {
-        out_file: BufWriter::new(out_file),
-        last_log: Instant::now(),
-        games_written: 0,
+    let (out_file, games_written) = if config.append {
+        open_for_append(&config.out_file).expect("Failed to open out file for appending")
+    } else {
+        let out_file = File::options()
+            .write(true)
+            .create_new(true)
+            .open(&config.out_file)
+            .expect("Failed to create out file");
+        (out_file, 0)
     };
-    let shared_state = Arc::new(Mutex::new(shared_state));
+
+    // Workers send finished games to a single writer thread, which owns the out file.
+    let (game_sink, games) = sync_channel(threads as usize * 4);
+    let writer_handle = std::thread::spawn(move || {
+        let mut out_file = BufWriter::new(out_file);
+        let mut games_written = games_written;
+        let mut last_log = Instant::now();
+        for analysis in games {
+            write_analyzed_game(&analysis, &mut out_file).unwrap();
+            // Flush every game so a killed run loses at most the games in progress.
+            out_file.flush().unwrap();
+            games_written += 1;
+            if games_written % GAMES_PER_LOG == 0 {
+                let now = Instant::now();
+                let elapsed = now.duration_since(last_log);
+                last_log = now;
+                eprintln!(
+                    "{} games written ({:.2} games/s).",
+                    games_written,
+                    GAMES_PER_LOG as f32 / elapsed.as_secs_f32()
+                );
+            }
+        }
+    });
     let mut thread_handles = Vec::with_capacity(threads as usize);
-    for _ in 0..threads {
+    for thread_index in 0..threads {
         let abort = Arc::clone(abort);
         let game_gen_config = game_gen_config.clone();
-        let shared_state = Arc::clone(&shared_state);
+        let game_sink = game_sink.clone();
+        let mut rng = match config.seed {
+            Some(seed) => Pcg64Mcg::seed_from_u64(seed.wrapping_add(thread_index as u64)),
+            None => Pcg64Mcg::from_entropy()
+        };
         let handle = std::thread::spawn(move || {
             while !abort.load(Ordering::SeqCst) {
-                let analysis = gen_game(&game_gen_config);
-                let mut shared_state = shared_state.lock().unwrap();
-                write_analyzed_game(&analysis, &mut shared_state.out_file).unwrap();
-                shared_state.games_written += 1;
-                if shared_state.games_written % GAMES_PER_LOG == 0 {
-                    let now = Instant::now();
-                    let elapsed = now.duration_since(shared_state.last_log);
-                    shared_state.last_log = now;
-                    eprintln!(
-                        "{} games written ({:.2} games/s).",
-                        shared_state.games_written,
-                        GAMES_PER_LOG as f32 / elapsed.as_secs_f32()
-                    );
-                }
+                let analysis = gen_game(&game_gen_config, &mut rng);
+                game_sink.send(analysis).unwrap();
             }
         });
         thread_handles.push(handle);
     }
+    drop(game_sink);
     for handle in thread_handles {
         handle.join().unwrap();
     }
+    writer_handle.join().unwrap();
 }
@@ -7,10 +7,14 @@ mod analyzed_game;
 mod game_gen;
 mod extract_positions;
 mod apply_syzygy;
+mod to_pgn;
+mod stats;
 
-use extract_positions::{ExtractPositionsConfig, run_position_extraction};
+use extract_positions::{ExtractPositionsConfig, run_position_extraction, VerifyFormatConfig, run_verify_format};
 use game_gen::{GameGenRunnerConfig, run_game_gen};
 use apply_syzygy::{ApplySyzygyConfig, run_apply_syzygy};
+use to_pgn::{ToPgnConfig, run_to_pgn};
+use stats::{StatsConfig, run_stats};
 
 #[derive(Parser)]
 /// Generate and process analyzed Tantabus games. 
@@ -23,7 +27,14 @@ struct DatagenCommand {
 enum DatagenSubcommand {
     GenGames(GameGenRunnerConfig),
     ExtractPos(ExtractPositionsConfig),
-    ApplySyzygy(ApplySyzygyConfig)
+    ApplySyzygy(ApplySyzygyConfig),
+    ToPgn(ToPgnConfig),
+    VerifyFormat(VerifyFormatConfig),
+    Stats(StatsConfig)
+}
+
+fn default_threads() -> u32 {
+    std::thread::available_parallelism().map_or(1, |t| t.get() as u32)
 }
 
 fn main() {
@@ -39,6 +50,9 @@ fn main() {
     match DatagenCommand::parse().subcommand {
         DatagenSubcommand::GenGames(config) => run_game_gen(&config, &abort),
         DatagenSubcommand::ExtractPos(config) => run_position_extraction(&config, &abort),
-        DatagenSubcommand::ApplySyzygy(config) => run_apply_syzygy(&config, &abort)
+        DatagenSubcommand::ApplySyzygy(config) => run_apply_syzygy(&config, &abort),
+        DatagenSubcommand::ToPgn(config) => run_to_pgn(&config, &abort),
+        DatagenSubcommand::VerifyFormat(config) => run_verify_format(&config, &abort),
+        DatagenSubcommand::Stats(config) => run_stats(&config, &abort)
     }
 }
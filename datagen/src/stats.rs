@@ -0,0 +1,88 @@
+use std::fs::File;
+use std::io::BufReader;
+use std::path::PathBuf;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
+
+use clap::Args;
+use cozy_chess::*;
+use tantabus::eval::EvalKind;
+
+use crate::analyzed_game::read_analyzed_game;
+
+/// Upper bounds of the eval histogram buckets, in absolute centipawns.
+const EVAL_BUCKETS: [i16; 8] = [25, 50, 100, 200, 400, 800, 1600, i16::MAX];
+
+#[derive(Debug, Args)]
+/// Summarize a file of analyzed games
+pub struct StatsConfig {
+    /// The input file
+    #[clap(short, long)]
+    in_file: PathBuf
+}
+
+pub fn run_stats(config: &StatsConfig, abort: &Arc<AtomicBool>) {
+    let in_file = File::open(&config.in_file).expect("Failed to open in file");
+    let mut in_file = BufReader::new(in_file);
+    let mut games = 0u64;
+    let mut plies = 0u64;
+    let mut positions = 0u64;
+    let mut white_wins = 0u64;
+    let mut black_wins = 0u64;
+    let mut draws = 0u64;
+    let mut tablebase_evals = 0u64;
+    let mut mate_evals = 0u64;
+    let mut eval_buckets = [0u64; EVAL_BUCKETS.len()];
+    loop {
+        let game = match read_analyzed_game(&mut in_file) {
+            Ok(Some(game)) => game,
+            Ok(None) => break,
+            Err(error) => {
+                eprintln!("Stopped at a truncated or corrupt game: {}", error);
+                break;
+            }
+        };
+        games += 1;
+        plies += game.moves.len() as u64;
+        positions += game.evals.len() as u64;
+        match game.winner {
+            Some(Color::White) => white_wins += 1,
+            Some(Color::Black) => black_wins += 1,
+            None => draws += 1
+        }
+        tablebase_evals += game.tablebase_evals.iter().filter(|&&tb| tb).count() as u64;
+        for eval in &game.evals {
+            match eval.kind() {
+                EvalKind::Centipawn(cp) => {
+                    let bucket = EVAL_BUCKETS.iter().position(|&max| cp.abs() < max).unwrap();
+                    eval_buckets[bucket] += 1;
+                }
+                _ => mate_evals += 1
+            }
+        }
+
+        if abort.load(Ordering::SeqCst) {
+            break;
+        }
+    }
+
+    let percent = |n: u64, total: u64| n as f64 * 100.0 / total.max(1) as f64;
+    println!("Games: {}", games);
+    println!("Positions: {}", positions);
+    println!("Average game length: {:.1} plies", plies as f64 / games.max(1) as f64);
+    println!("White wins: {} ({:.1}%)", white_wins, percent(white_wins, games));
+    println!("Black wins: {} ({:.1}%)", black_wins, percent(black_wins, games));
+    println!("Draws: {} ({:.1}%)", draws, percent(draws, games));
+    println!("Tablebase evals: {} ({:.1}%)", tablebase_evals, percent(tablebase_evals, positions));
+    println!("Eval distribution:");
+    let mut min = 0;
+    for (&max, &count) in EVAL_BUCKETS.iter().zip(&eval_buckets) {
+        if max == i16::MAX {
+            println!("  |cp| >= {}: {} ({:.1}%)", min, count, percent(count, positions));
+        } else {
+            println!("  {} <= |cp| < {}: {} ({:.1}%)", min, max, count, percent(count, positions));
+        }
+        min = max;
+    }
+    println!("  mate: {} ({:.1}%)", mate_evals, percent(mate_evals, positions));
+}
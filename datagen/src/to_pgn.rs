@@ -0,0 +1,111 @@
+use std::fs::File;
+use std::io::{Write, BufReader, BufWriter};
+use std::path::PathBuf;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
+
+use clap::Args;
+use cozy_chess::*;
+use tantabus::eval::{Eval, EvalKind};
+use tantabus::san::move_to_san;
+
+use crate::analyzed_game::{AnalyzedGame, read_analyzed_game};
+
+const MAX_LINE_LENGTH: usize = 80;
+
+#[derive(Debug, Args)]
+/// Convert analyzed games to PGN
+pub struct ToPgnConfig {
+    /// The input file
+    #[clap(short, long)]
+    in_file: PathBuf,
+
+    /// The output file
+    #[clap(short, long)]
+    out_file: PathBuf
+}
+
+/// Format an eval from white's perspective as a PGN comment.
+fn eval_comment(eval: Eval) -> String {
+    match eval.kind() {
+        EvalKind::MatedIn(_) => format!("{{{}}}", eval),
+        EvalKind::Centipawn(cp) if cp < 0 => format!("{{{}}}", eval),
+        _ => format!("{{+{}}}", eval)
+    }
+}
+
+fn write_pgn(out: &mut impl Write, game: &AnalyzedGame, round: u64) -> std::io::Result<()> {
+    let result = match game.winner {
+        Some(Color::White) => "1-0",
+        Some(Color::Black) => "0-1",
+        None => "1/2-1/2"
+    };
+    writeln!(out, "[Event \"Tantabus datagen\"]")?;
+    writeln!(out, "[Site \"?\"]")?;
+    writeln!(out, "[Date \"????.??.??\"]")?;
+    writeln!(out, "[Round \"{}\"]", round)?;
+    writeln!(out, "[White \"Tantabus\"]")?;
+    writeln!(out, "[Black \"Tantabus\"]")?;
+    writeln!(out, "[Result \"{}\"]", result)?;
+    writeln!(out, "[PlyCount \"{}\"]", game.moves.len())?;
+    if game.start_pos.hash() != Board::default().hash() {
+        writeln!(out, "[SetUp \"1\"]")?;
+        writeln!(out, "[FEN \"{}\"]", game.start_pos)?;
+    }
+    writeln!(out)?;
+
+    let mut tokens = Vec::new();
+    let mut board = game.start_pos.clone();
+    for (i, &mv) in game.moves.iter().enumerate() {
+        if board.side_to_move() == Color::White {
+            tokens.push(format!("{}.", board.fullmove_number()));
+        } else if i == 0 {
+            tokens.push(format!("{}...", board.fullmove_number()));
+        }
+        tokens.push(move_to_san(&board, mv));
+        // The randomized opening moves weren't analyzed.
+        if let Some(i) = i.checked_sub(game.opening_moves as usize) {
+            tokens.push(eval_comment(game.evals[i]));
+        }
+        board.play_unchecked(mv);
+    }
+    tokens.push(result.to_owned());
+
+    let mut line_length = 0;
+    for token in tokens {
+        if line_length > 0 && line_length + 1 + token.len() > MAX_LINE_LENGTH {
+            writeln!(out)?;
+            line_length = 0;
+        }
+        if line_length > 0 {
+            write!(out, " ")?;
+            line_length += 1;
+        }
+        write!(out, "{}", token)?;
+        line_length += token.len();
+    }
+    writeln!(out)?;
+    writeln!(out)?;
+    Ok(())
+}
+
+pub fn run_to_pgn(config: &ToPgnConfig, abort: &Arc<AtomicBool>) {
+    let in_file = File::open(&config.in_file).expect("Failed to open in file");
+    let out_file = File::options()
+        .write(true)
+        .create_new(true)
+        .open(&config.out_file)
+        .expect("Failed to create out file");
+    let mut in_file = BufReader::new(in_file);
+    let mut out_file = BufWriter::new(out_file);
+    let mut games = 0u64;
+    while let Some(game) = read_analyzed_game(&mut in_file).unwrap() {
+        games += 1;
+        write_pgn(&mut out_file, &game, games).unwrap();
+
+        if abort.load(Ordering::SeqCst) {
+            break;
+        }
+    }
+    eprintln!("Wrote {} games.", games);
+}
@@ -23,7 +23,7 @@ impl Display for EvalKind {
                 if cp < 0 {
                     write!(f, "-")?;
                 }
-                write!(f, "{}.{}", cp.abs() / 100, cp.abs() % 100)
+                write!(f, "{}.{:02}", cp.abs() / 100, cp.abs() % 100)
             },
             EvalKind::MateIn(m) => write!(f, "M{}", (m + 1) / 2),
             EvalKind::MatedIn(m) => write!(f, "-M{}", (m + 1) / 2)
@@ -64,6 +64,35 @@ impl Eval {
         Self(-Self::mate_in(plies_to_mate).0)
     }
 
+    /// The largest centipawn score that leaves room under `cap` for every mate distance.
+    /// A cap of 256 or less leaves no room, so centipawn scores all become zero.
+    const fn max_cp_below(cap: i16) -> i16 {
+        let max_cp = cap as i32 - (u8::MAX as i32 + 1);
+        if max_cp > 0 { max_cp as i16 } else { 0 }
+    }
+
+    /// A large centipawn score standing in for a mate in `plies_to_mate` plies.
+    /// Faster mates score closer to `cap`. Mates too far away to fit saturate to the
+    /// largest centipawn score [`Eval::clamp_to_cp`] allows, so they never score below it.
+    pub const fn mate_in_as_cp(cap: i16, plies_to_mate: u32) -> Self {
+        let max_cp = Self::max_cp_below(cap);
+        let cp = cap as i64 - plies_to_mate as i64;
+        Self(if cp > max_cp as i64 { cp as i16 } else { max_cp })
+    }
+
+    /// Convert mate scores to centipawn scores, for consumers that don't want mate notation.
+    /// Mates map to scores just under `cap`, and centipawn scores are clamped to stay below them.
+    pub const fn clamp_to_cp(self, cap: i16) -> Self {
+        let max_cp = Self::max_cp_below(cap);
+        match self.kind() {
+            EvalKind::Centipawn(cp) if cp > max_cp => Self(max_cp),
+            EvalKind::Centipawn(cp) if cp < -max_cp => Self(-max_cp),
+            EvalKind::Centipawn(cp) => Self(cp),
+            EvalKind::MateIn(p) => Self::mate_in_as_cp(cap, p as u32),
+            EvalKind::MatedIn(p) => Self(-Self::mate_in_as_cp(cap, p as u32).0)
+        }
+    }
+
     pub const fn kind(self) -> EvalKind {
         match self.0 {
             v if v >= Self::MAX_MATE_IN.0 => EvalKind::MateIn((Self::MIN_MATE_IN.0 - v) as u8),
@@ -80,10 +109,14 @@ impl Eval {
         }
     }
 
+    /// Encode as a little-endian `i16`, as stored in the cache table and datagen files.
+    /// Centipawn scores are stored as is. A mate in `n` plies is stored as `32667 - n`
+    /// (`i16::MAX - 100 - n`), and being mated in `n` plies as its negation.
     pub const fn to_bytes(self) -> [u8; 2] {
         self.0.to_le_bytes()
     }
 
+    /// Decode an eval encoded by [`Eval::to_bytes`].
     pub const fn from_bytes(bytes: [u8; 2]) -> Self {
         Self(i16::from_le_bytes(bytes))
     }
@@ -150,3 +183,72 @@ impl_saturating_math_ops! {
     saturating_sub,
     saturating_mul
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const CAP: i16 = 1000;
+    // CAP minus room for all 256 mate distances.
+    const MAX_CP: Eval = Eval::cp(744);
+
+    #[test]
+    fn mate_in_one_as_cp() {
+        assert_eq!(Eval::mate_in_as_cp(CAP, 1), Eval::cp(999));
+        assert_eq!(Eval::mate_in(1).clamp_to_cp(CAP), Eval::cp(999));
+        assert_eq!(Eval::mated_in(1).clamp_to_cp(CAP), Eval::cp(-999));
+    }
+
+    #[test]
+    fn mate_at_cap_as_cp() {
+        // The furthest mate that has its own score is still above every centipawn score.
+        assert_eq!(Eval::mate_in(u8::MAX).clamp_to_cp(CAP), Eval::cp(745));
+        assert_eq!(Eval::cp(5000).clamp_to_cp(CAP), MAX_CP);
+        assert_eq!(Eval::cp(-5000).clamp_to_cp(CAP), -MAX_CP);
+        assert_eq!(Eval::mate_in_as_cp(CAP, CAP as u32), MAX_CP);
+    }
+
+    #[test]
+    fn mate_beyond_cap_as_cp() {
+        assert_eq!(Eval::mate_in_as_cp(CAP, u8::MAX as u32 + 1), MAX_CP);
+        assert_eq!(Eval::mate_in_as_cp(CAP, 5000), MAX_CP);
+        assert_eq!(Eval::mate_in_as_cp(CAP, u32::MAX), MAX_CP);
+    }
+
+    #[test]
+    fn small_cap_as_cp() {
+        // There's no room for centipawn scores under the cap, but mates keep their order.
+        assert_eq!(Eval::cp(50).clamp_to_cp(200), Eval::ZERO);
+        assert_eq!(Eval::cp(-50).clamp_to_cp(200), Eval::ZERO);
+        assert_eq!(Eval::mate_in(1).clamp_to_cp(200), Eval::cp(199));
+        assert_eq!(Eval::mate_in(250).clamp_to_cp(200), Eval::ZERO);
+        assert_eq!(Eval::mated_in(1).clamp_to_cp(200), Eval::cp(-199));
+        assert_eq!(Eval::mate_in(1).clamp_to_cp(-10), Eval::ZERO);
+        assert_eq!(Eval::mate_in_as_cp(i16::MIN, 0), Eval::ZERO);
+    }
+
+    #[test]
+    fn bytes_round_trip() {
+        let evals = [
+            Eval::ZERO,
+            Eval::cp(35),
+            Eval::cp(-1250),
+            Eval::mate_in(1),
+            Eval::mate_in(u8::MAX),
+            Eval::mated_in(0),
+            Eval::mated_in(7)
+        ];
+        for eval in evals {
+            assert_eq!(Eval::from_bytes(eval.to_bytes()), eval, "{}", eval);
+        }
+    }
+
+    #[test]
+    fn bytes_encoding() {
+        assert_eq!(Eval::cp(-1250).to_bytes(), (-1250i16).to_le_bytes());
+        assert_eq!(Eval::mate_in(3).to_bytes(), (32667i16 - 3).to_le_bytes());
+        assert_eq!(Eval::mated_in(3).to_bytes(), (-(32667i16 - 3)).to_le_bytes());
+        assert_eq!(Eval::from_bytes(32660i16.to_le_bytes()).kind(), EvalKind::MateIn(7));
+        assert_eq!(Eval::from_bytes((-32660i16).to_le_bytes()).kind(), EvalKind::MatedIn(7));
+    }
+}
@@ -2,3 +2,7 @@ pub mod search;
 pub mod eval;
 pub mod time;
 pub mod nnue;
+pub mod san;
+mod perft;
+
+pub use perft::perft;
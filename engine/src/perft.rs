@@ -0,0 +1,77 @@
+use cozy_chess::*;
+
+// CITE: Perft.
+// https://www.chessprogramming.org/Perft
+/// Count the leaf nodes of the legal move tree to a given depth.
+pub fn perft(board: &Board, depth: u8) -> u64 {
+    if depth == 0 {
+        return 1;
+    }
+    let mut nodes = 0;
+    if depth == 1 {
+        board.generate_moves(|moves| {
+            nodes += moves.len() as u64;
+            false
+        });
+    } else {
+        board.generate_moves(|moves| {
+            for mv in moves {
+                let mut child = board.clone();
+                child.play_unchecked(mv);
+                nodes += perft(&child, depth - 1);
+            }
+            false
+        });
+    }
+    nodes
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn test_perft(fen: &str, chess960: bool, counts: &[u64]) {
+        let board = Board::from_fen(fen, chess960).unwrap();
+        for (depth, &count) in counts.iter().enumerate() {
+            let depth = depth as u8 + 1;
+            assert_eq!(perft(&board, depth), count, "{} at depth {}", fen, depth);
+        }
+    }
+
+    const STARTPOS: &str = "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1";
+    const KIWIPETE: &str = "r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 1";
+    const CHESS960: &str = "bqnb1rkr/pp3ppp/3ppn2/2p5/5P2/P2P4/NPP1P1PP/BQ1BNRKR w HFhf - 2 9";
+
+    #[test]
+    fn startpos() {
+        test_perft(STARTPOS, false, &[20, 400, 8902, 197281]);
+    }
+
+    #[test]
+    #[ignore]
+    fn startpos_deep() {
+        test_perft(STARTPOS, false, &[20, 400, 8902, 197281, 4865609]);
+    }
+
+    #[test]
+    fn kiwipete() {
+        test_perft(KIWIPETE, false, &[48, 2039, 97862]);
+    }
+
+    #[test]
+    #[ignore]
+    fn kiwipete_deep() {
+        test_perft(KIWIPETE, false, &[48, 2039, 97862, 4085603]);
+    }
+
+    #[test]
+    fn chess960() {
+        test_perft(CHESS960, true, &[21, 528, 12189]);
+    }
+
+    #[test]
+    #[ignore]
+    fn chess960_deep() {
+        test_perft(CHESS960, true, &[21, 528, 12189, 326672, 8146062]);
+    }
+}
@@ -0,0 +1,175 @@
+use cozy_chess::*;
+
+fn parse_piece(c: char) -> Option<Piece> {
+    Some(match c {
+        'N' => Piece::Knight,
+        'B' => Piece::Bishop,
+        'R' => Piece::Rook,
+        'Q' => Piece::Queen,
+        'K' => Piece::King,
+        _ => return None
+    })
+}
+
+fn parse_rank(c: char) -> Option<Rank> {
+    let rank = c.to_digit(10)? as usize;
+    if (1..=8).contains(&rank) {
+        Some(Rank::index(rank - 1))
+    } else {
+        None
+    }
+}
+
+/// Parse a move in standard algebraic notation.
+/// Returns `None` if the move is malformed, illegal, or ambiguous.
+pub fn san_to_move(board: &Board, san: &str) -> Option<Move> {
+    let san = san.trim_end_matches(|c| matches!(c, '+' | '#' | '!' | '?'));
+    let color = board.side_to_move();
+    let mut candidates = Vec::new();
+
+    if let Some(short) = match san {
+        "O-O" | "0-0" => Some(true),
+        "O-O-O" | "0-0-0" => Some(false),
+        _ => None
+    } {
+        // Castling is encoded as the king capturing its own rook.
+        board.generate_moves(|moves| {
+            if moves.piece == Piece::King {
+                for mv in moves {
+                    let is_castle = board.color_on(mv.to) == Some(color);
+                    if is_castle && (mv.to.file() > mv.from.file()) == short {
+                        candidates.push(mv);
+                    }
+                }
+            }
+            false
+        });
+    } else {
+        let mut chars = san.chars().collect::<Vec<_>>();
+        let mut promotion = None;
+        if let Some(&last) = chars.last() {
+            if let Some(piece) = parse_piece(last).filter(|&p| p != Piece::King) {
+                promotion = Some(piece);
+                chars.pop();
+                if chars.last() == Some(&'=') {
+                    chars.pop();
+                }
+            }
+        }
+        let piece = match chars.first().copied().and_then(parse_piece) {
+            Some(piece) => {
+                chars.remove(0);
+                piece
+            }
+            None => Piece::Pawn
+        };
+        chars.retain(|&c| c != 'x' && c != '-');
+        if chars.len() < 2 {
+            return None;
+        }
+        let to_rank = parse_rank(chars.pop()?)?;
+        let to_file = File::try_from(chars.pop()?).ok()?;
+        let to = Square::new(to_file, to_rank);
+        let mut from_file = None;
+        let mut from_rank = None;
+        for c in chars {
+            if let Some(rank) = parse_rank(c) {
+                from_rank = Some(rank);
+            } else {
+                from_file = Some(File::try_from(c).ok()?);
+            }
+        }
+        board.generate_moves(|moves| {
+            if moves.piece == piece {
+                for mv in moves {
+                    let matches = mv.to == to
+                        && mv.promotion == promotion
+                        && board.color_on(mv.to) != Some(color)
+                        && from_file.map_or(true, |file| mv.from.file() == file)
+                        && from_rank.map_or(true, |rank| mv.from.rank() == rank);
+                    if matches {
+                        candidates.push(mv);
+                    }
+                }
+            }
+            false
+        });
+    }
+
+    if candidates.len() == 1 {
+        candidates.pop()
+    } else {
+        None
+    }
+}
+
+fn piece_char(piece: Piece) -> char {
+    match piece {
+        Piece::Pawn => 'P',
+        Piece::Knight => 'N',
+        Piece::Bishop => 'B',
+        Piece::Rook => 'R',
+        Piece::Queen => 'Q',
+        Piece::King => 'K'
+    }
+}
+
+/// Format a legal move in standard algebraic notation.
+pub fn move_to_san(board: &Board, mv: Move) -> String {
+    let color = board.side_to_move();
+    let piece = board.piece_on(mv.from).unwrap();
+    let mut san = String::new();
+    if board.color_on(mv.to) == Some(color) {
+        // Castling is encoded as the king capturing its own rook.
+        san.push_str(if mv.to.file() > mv.from.file() { "O-O" } else { "O-O-O" });
+    } else if piece == Piece::Pawn {
+        if mv.from.file() != mv.to.file() {
+            san.push(mv.from.file().into());
+            san.push('x');
+        }
+        san.push_str(&mv.to.to_string());
+        if let Some(promotion) = mv.promotion {
+            san.push('=');
+            san.push(piece_char(promotion));
+        }
+    } else {
+        san.push(piece_char(piece));
+        let mut ambiguous = false;
+        let mut same_file = false;
+        let mut same_rank = false;
+        board.generate_moves(|moves| {
+            if moves.piece == piece {
+                for other in moves {
+                    if other.to == mv.to && other.from != mv.from {
+                        ambiguous = true;
+                        same_file |= other.from.file() == mv.from.file();
+                        same_rank |= other.from.rank() == mv.from.rank();
+                    }
+                }
+            }
+            false
+        });
+        if ambiguous {
+            if !same_file {
+                san.push(mv.from.file().into());
+            } else if !same_rank {
+                san.push((b'1' + mv.from.rank() as u8) as char);
+            } else {
+                san.push_str(&mv.from.to_string());
+            }
+        }
+        if board.color_on(mv.to).is_some() {
+            san.push('x');
+        }
+        san.push_str(&mv.to.to_string());
+    }
+
+    let mut child = board.clone();
+    child.play_unchecked(mv);
+    if child.status() == GameStatus::Won {
+        san.push('#');
+    } else if !child.checkers().is_empty() {
+        san.push('+');
+    }
+    san
+}
@@ -0,0 +1,46 @@
+use std::sync::atomic::{AtomicU32, Ordering};
+
+use cozy_chess::*;
+
+/// The best root move found so far, readable from any thread while a search runs.
+///
+/// The move is packed into a single atomic word, so a read never sees a torn move.
+/// Nothing else is published through it, so relaxed ordering is enough:
+/// a reader may see a slightly stale move, but always one the search did pick at some point.
+#[derive(Debug)]
+pub struct BestMoveCell(AtomicU32);
+
+impl BestMoveCell {
+    const NONE: u32 = u32::MAX;
+
+    pub fn new() -> Self {
+        Self(AtomicU32::new(Self::NONE))
+    }
+
+    /// The best move so far, or `None` if the search hasn't found one yet.
+    pub fn get(&self) -> Option<Move> {
+        let packed = self.0.load(Ordering::Relaxed);
+        if packed == Self::NONE {
+            return None;
+        }
+        Some(Move {
+            from: Square::index((packed & 0b111111) as usize),
+            to: Square::index((packed >> 6 & 0b111111) as usize),
+            promotion: Piece::try_index((packed >> 12) as usize)
+        })
+    }
+
+    pub(crate) fn set(&self, mv: Option<Move>) {
+        let packed = mv.map_or(Self::NONE, |mv| {
+            let promotion = mv.promotion.map_or(Piece::NUM as u32, |p| p as u32);
+            mv.from as u32 | (mv.to as u32) << 6 | promotion << 12
+        });
+        self.0.store(packed, Ordering::Relaxed);
+    }
+}
+
+impl Default for BestMoveCell {
+    fn default() -> Self {
+        Self::new()
+    }
+}
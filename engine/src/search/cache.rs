@@ -117,7 +117,7 @@ impl CacheTable {
     /// There must be at most [`u32::MAX`] entries.
     /// If not, this will error with [`CacheTableError::TooManyEntries`].
     pub fn new_with_size(size: usize) -> Result<Self, CacheTableError> {
-        let entries = size / std::mem::size_of::<CacheEntry>();
+        let entries = size / Self::entry_size();
         let entries: u32 = entries.try_into()
             .map_err(|_| CacheTableError::TooManyEntries)?;
         let entries = entries.try_into()
@@ -144,6 +144,23 @@ impl CacheTable {
         let _ = entry;
     }
 
+    /// Look up the entry for a position without adjusting mate scores.
+    /// Mate scores are stored relative to the position itself,
+    /// not to the root of the search that stored them as [`Self::get`] returns them.
+    pub fn peek(&self, board: &Board) -> Option<CacheData> {
+        let hash = board.hash();
+        let index = self.hash_to_index(hash);
+        self.table[index].load(hash)
+    }
+
+    /// Look up the entry for a position as if it were the root of a search.
+    /// Equivalent to [`Self::get`] with a ply index of zero.
+    pub fn probe(&self, board: &Board) -> Option<CacheData> {
+        self.get(board, 0)
+    }
+
+    /// Look up the entry for a position `ply_index` plies from the root.
+    /// Mate scores are adjusted to be relative to the root.
     pub fn get(&self, board: &Board, ply_index: u8) -> Option<CacheData> {
         let hash = board.hash();
         let index = self.hash_to_index(hash);
@@ -161,7 +178,7 @@ impl CacheTable {
                     if p <= u8::MAX as u32 {
                         Eval::mate_in(p as u8)
                     } else {
-                        Eval::cp((20000 - p - u8::MAX as u32) as i16)
+                        Eval::mate_in_as_cp(20000 - u8::MAX as i16, p)
                     }
                 },
                 EvalKind::MatedIn(p) => {
@@ -169,7 +186,7 @@ impl CacheTable {
                     if p <= u8::MAX as u32 {
                         Eval::mated_in(p as u8)
                     } else {
-                        Eval::cp(-((20000 - p - u8::MAX as u32) as i16))
+                        -Eval::mate_in_as_cp(20000 - u8::MAX as i16, p)
                     }
                 },
             };
@@ -193,6 +210,16 @@ impl CacheTable {
         self.table.len() as u32
     }
 
+    /// The size of a single entry in bytes.
+    pub const fn entry_size() -> usize {
+        std::mem::size_of::<CacheEntry>()
+    }
+
+    /// The size of the whole table in bytes.
+    pub fn size(&self) -> usize {
+        self.table.len() * Self::entry_size()
+    }
+
     pub fn approx_size_permill(&self) -> u32 {
         self.table.iter().take(1000).filter(|e| !e.is_empty()).count() as u32
     }
@@ -7,3 +7,109 @@ pub fn move_is_capture(mv: Move, board: &Board) -> bool {
 pub fn move_is_quiet(mv: Move, board: &Board) -> bool {
     !move_is_capture(mv, board) && mv.promotion.is_none()
 }
+
+/// A fixed ordering of moves by their from square, then to square, then promotion.
+pub fn move_order_key(mv: Move) -> (u8, u8, u8) {
+    (mv.from as u8, mv.to as u8, mv.promotion.map_or(0, |p| p as u8 + 1))
+}
+
+/// Count how many times `board` occurred in `history`,
+/// where `history` holds the hashes of the positions before `board`, oldest first.
+/// Only positions since the last irreversible move with the same side to move are considered.
+/// A result of 1 is a twofold repetition and 2 is a threefold repetition.
+pub fn count_repetitions(history: &[u64], board: &Board) -> usize {
+    history.iter()
+        .rev()
+        .take(board.halfmove_clock() as usize)
+        .skip(1) // The previous ply has the other side to move
+        .step_by(2) // Every second ply so it's our turn
+        .filter(|&&hash| hash == board.hash())
+        .count()
+}
+
+/// Whether `board` is scored as a draw by repetition during search,
+/// where `history` holds the hashes of the positions before `board`, oldest first,
+/// and the search's root is at index `root_index`.
+/// A twofold repetition inside the tree is a draw, since if repeating were good for
+/// either side, they could repeat again, and it's much cheaper than waiting for threefold.
+/// Positions from before the root were actually played, so repeating one of them
+/// is only a draw if it makes a real threefold repetition.
+pub fn is_repetition_draw(history: &[u64], root_index: usize, board: &Board) -> bool {
+    let in_tree = &history[root_index.min(history.len())..];
+    count_repetitions(in_tree, board) > 0 || count_repetitions(history, board) > 1
+}
+
+/// A small seeded source of randomness for diversifying helper threads.
+/// It's stateless, so a given seed, position and move always produce the same value,
+/// regardless of the order nodes are visited in.
+#[derive(Debug, Clone)]
+pub struct SearchRng {
+    seed: u64
+}
+
+impl SearchRng {
+    pub fn new(seed: u64) -> Self {
+        Self { seed }
+    }
+
+    fn hash(&self, board: &Board, mv: Move) -> u64 {
+        let promotion = mv.promotion.map_or(0, |p| p as u64 + 1);
+        let mv = mv.from as u64 | (mv.to as u64) << 6 | promotion << 12;
+        // CITE: This is the SplitMix64 finalizer.
+        // https://prng.di.unimi.it/splitmix64.c
+        let mut x = self.seed.wrapping_mul(0x9E3779B97F4A7C15) ^ board.hash() ^ mv;
+        x = (x ^ (x >> 30)).wrapping_mul(0xBF58476D1CE4E5B9);
+        x = (x ^ (x >> 27)).wrapping_mul(0x94D049BB133111EB);
+        x ^= x >> 31;
+        x
+    }
+
+    pub fn tie_break(&self, board: &Board, mv: Move) -> u8 {
+        self.hash(board, mv) as u8
+    }
+
+    /// A value from `0` to `max` inclusive.
+    pub fn noise(&self, board: &Board, mv: Move, max: u16) -> u16 {
+        (self.hash(board, mv) % (max as u64 + 1)) as u16
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// The hashes of the positions before the last move, and the position after it.
+    fn play(moves: &[&str]) -> (Vec<u64>, Board) {
+        let mut board = Board::default();
+        let mut history = Vec::new();
+        for mv in moves {
+            history.push(board.hash());
+            board.play(mv.parse().unwrap());
+        }
+        (history, board)
+    }
+
+    #[test]
+    fn repetition_once_before_root() {
+        // The start position repeats, but it only occurred once, before the root.
+        let (history, board) = play(&["g1f3", "g8f6", "f3g1", "f6g8"]);
+        assert!(!is_repetition_draw(&history, 3, &board));
+    }
+
+    #[test]
+    fn repetition_twice_before_root() {
+        // The start position occurred twice before the root, so repeating it is threefold.
+        let (history, board) = play(&[
+            "g1f3", "g8f6", "f3g1", "f6g8",
+            "g1f3", "g8f6", "f3g1", "f6g8"
+        ]);
+        assert!(is_repetition_draw(&history, 7, &board));
+    }
+
+    #[test]
+    fn repetition_in_tree() {
+        // The root (after g1f3) repeats inside the tree.
+        let (history, board) = play(&["g1f3", "g8f6", "f3g1", "f6g8", "g1f3"]);
+        assert!(is_repetition_draw(&history, 1, &board));
+    }
+}
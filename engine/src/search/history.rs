@@ -1,5 +1,7 @@
 use cozy_chess::*;
 
+const MAX_HISTORY: i32 = 512;
+
 pub struct HistoryTable([[[i32; Square::NUM]; Piece::NUM]; Color::NUM]);
 
 impl HistoryTable {
@@ -14,19 +16,98 @@ impl HistoryTable {
             [mv.to as usize]
     }
 
-    pub fn update(&mut self, board: &Board, mv: Move, depth: u8, cutoff: bool) {
+    /// Reward or punish a move. With `gravity`, the change shrinks as the entry approaches
+    /// the maximum, so entries settle smoothly instead of piling up against the clamp.
+    pub fn update(&mut self, board: &Board, mv: Move, depth: u8, cutoff: bool, gravity: bool) {
         let history = &mut self.0
             [board.side_to_move() as usize]
             [board.piece_on(mv.from).unwrap() as usize]
             [mv.to as usize];
         let change = depth as i32 * depth as i32;
-        let decay = change * *history / 512;
-        if cutoff {
-            *history += change;
+        if gravity {
+            // CITE: History gravity.
+            // https://www.chessprogramming.org/History_Heuristic
+            let change = change.min(MAX_HISTORY);
+            let change = if cutoff { change } else { -change };
+            *history += change - *history * change.abs() / MAX_HISTORY;
         } else {
-            *history -= change;
+            let decay = change * *history / MAX_HISTORY;
+            if cutoff {
+                *history += change;
+            } else {
+                *history -= change;
+            }
+            *history -= decay;
+        }
+        *history = (*history).clamp(-MAX_HISTORY, MAX_HISTORY);
+    }
+
+    /// Decay all entries so that old history still counts but is easier to override.
+    pub fn age(&mut self) {
+        for history in self.0.iter_mut().flatten().flatten() {
+            *history /= 2;
+        }
+    }
+
+    /// Every entry as `(color, piece, to square, value)`, for inspecting what the search learned.
+    pub fn iter(&self) -> impl Iterator<Item=(Color, Piece, Square, i32)> + '_ {
+        Color::ALL.iter().flat_map(move |&color| {
+            Piece::ALL.iter().flat_map(move |&piece| {
+                Square::ALL.iter().map(move |&square| {
+                    let value = self.0[color as usize][piece as usize][square as usize];
+                    (color, piece, square, value)
+                })
+            })
+        })
+    }
+}
+
+impl Default for HistoryTable {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn gravity_converges() {
+        let board = Board::default();
+        let good = "g1f3".parse().unwrap();
+        let bad = "b1c3".parse().unwrap();
+        let mut history = HistoryTable::new();
+        let mut prev = 0;
+        for _ in 0..100 {
+            history.update(&board, good, 8, true, true);
+            history.update(&board, bad, 8, false, true);
+            let value = history.get(&board, good);
+            assert!(value >= prev && value <= MAX_HISTORY);
+            assert_eq!(history.get(&board, bad), -value);
+            prev = value;
+        }
+        assert_eq!(prev, MAX_HISTORY);
+
+        // Further rewards leave it at the maximum instead of piling up.
+        history.update(&board, good, 20, true, true);
+        assert_eq!(history.get(&board, good), MAX_HISTORY);
+    }
+
+    #[test]
+    fn age_halves() {
+        let board = Board::default();
+        let good = "g1f3".parse().unwrap();
+        let bad = "b1c3".parse().unwrap();
+        let mut history = HistoryTable::new();
+        history.update(&board, good, 4, true, false);
+        history.update(&board, bad, 4, false, false);
+        let before = history.iter().collect::<Vec<_>>();
+        history.age();
+        for ((_, _, _, old), (_, _, _, new)) in before.iter().zip(history.iter()) {
+            assert_eq!(new, old / 2);
         }
-        *history -= decay;
-        *history = (*history).clamp(-512, 512);
+        assert_eq!(history.get(&board, good), 8);
+        assert_eq!(history.get(&board, bad), -8);
     }
 }
@@ -1,5 +1,6 @@
 use std::convert::TryInto;
 use std::num::{NonZeroU8, NonZeroU32};
+use std::sync::Arc;
 use std::sync::atomic::{AtomicBool, Ordering};
 
 use cozy_chess::*;
@@ -16,15 +17,36 @@ mod oracle;
 mod history;
 mod params;
 mod position;
+mod best_move;
 
 use search::*;
+pub use search::MAX_PLY;
 pub use params::*;
-pub use cache::{CacheTable, CacheData};
+pub use cache::{CacheTable, CacheData, CacheDataKind};
+pub use moves::{SeeScore, static_exchange_evaluation};
+pub use helpers::count_repetitions;
+pub use best_move::BestMoveCell;
+pub use history::HistoryTable;
 use position::Position;
 
 pub trait SearchHandler {
     fn stop_search(&self, nodes: u64) -> bool;
     fn new_result(&mut self, result: SearchResult);
+
+    /// Called periodically during a search with the node count
+    /// and depth of the iteration in progress. Does nothing by default.
+    fn progress(&mut self, _nodes: u64, _depth: u8) {}
+
+    /// Called when the root search fails outside its aspiration window and is about to be redone.
+    /// The result's eval is only a bound, and its principal variation is just the best move so far.
+    /// Does nothing by default.
+    fn window_failed(&mut self, _result: SearchResult) {}
+
+    /// Called with each completed iteration's result before it's passed to `new_result`.
+    /// Returning `false` ends the search cleanly after that iteration. Always continues by default.
+    fn continue_deepening(&self, _last: &SearchResult) -> bool {
+        true
+    }
 }
 
 impl<H: SearchHandler, R: std::ops::DerefMut<Target=H>> SearchHandler for R {
@@ -35,12 +57,27 @@ impl<H: SearchHandler, R: std::ops::DerefMut<Target=H>> SearchHandler for R {
     fn new_result(&mut self, search_result: SearchResult) {
         (**self).new_result(search_result)
     }
+
+    fn progress(&mut self, nodes: u64, depth: u8) {
+        (**self).progress(nodes, depth)
+    }
+
+    fn window_failed(&mut self, result: SearchResult) {
+        (**self).window_failed(result)
+    }
+
+    fn continue_deepening(&self, last: &SearchResult) -> bool {
+        (**self).continue_deepening(last)
+    }
 }
 
 #[derive(Debug, Clone)]
 pub struct SearchResult {
-    pub mv: Move,
+    /// The best move, or `None` if there are no legal moves.
+    pub mv: Option<Move>,
     pub eval: Eval,
+    /// Whether `eval` is exact or only a bound on the true score.
+    pub bound: CacheDataKind,
     pub nodes: u64,
     pub depth: u8,
     pub seldepth: u8,
@@ -60,10 +97,39 @@ impl SearchHandler for WorkerHandler<'_> {
     fn new_result(&mut self, _result: SearchResult) {}
 }
 
+/// Deliberate inaccuracy in picking the root move, for weakening the engine.
+/// Each root move gets up to `max` centipawns added to its eval when picking the best one,
+/// so moves close to the best are sometimes picked instead, and worse moves more rarely.
+/// The noise is only used to pick the move; it isn't part of the reported eval.
+#[derive(Debug, Clone)]
+pub struct RootNoise {
+    pub max: u16,
+    /// Different seeds pick different moves. The same seed always picks the same move.
+    pub seed: u64
+}
+
+impl RootNoise {
+    fn noise(&self, board: &Board, mv: Move) -> Eval {
+        let noise = helpers::SearchRng::new(self.seed).noise(board, mv, self.max);
+        Eval::cp(noise.min(i16::MAX as u16) as i16)
+    }
+}
+
 #[derive(Debug, Clone)]
 pub struct EngineOptions {
+    /// The maximum depth to iterate to.
+    /// Extensions can search past this, but never past [`MAX_PLY`].
     pub max_depth: NonZeroU8,
-    pub threads: NonZeroU32
+    pub threads: NonZeroU32,
+    /// Pin each helper thread to its own core.
+    /// This does nothing unless the `affinity` feature is enabled.
+    pub pin_threads: bool,
+    /// Noise for weakening root move selection. `None` always picks the best move.
+    pub root_noise: Option<RootNoise>,
+    /// Break ties between equally good root moves by picking the one with the lowest
+    /// from and to squares, rather than whichever move ordering happened to try first.
+    /// This makes games more reproducible at the cost of some extra searching.
+    pub break_root_ties: bool
 }
 
 impl Default for EngineOptions {
@@ -71,17 +137,54 @@ impl Default for EngineOptions {
         Self {
             max_depth: 64.try_into().unwrap(),
             threads: 1.try_into().unwrap(),
+            pin_threads: false,
+            root_noise: None,
+            break_root_ties: false
         }
     }
 }
 
+/// What the search threads learned, such as history heuristics,
+/// which can be carried over to later searches in the same game.
+#[derive(Default)]
+pub struct SearchMemory {
+    search_data: Vec<SearchData>
+}
+
+impl SearchMemory {
+    /// The main thread's history for quiet moves, if it's searched yet.
+    pub fn quiet_history(&self) -> Option<&HistoryTable> {
+        self.search_data.first().map(|data| &data.quiet_history)
+    }
+
+    /// The main thread's history for captures, if it's searched yet.
+    pub fn capture_history(&self) -> Option<&HistoryTable> {
+        self.search_data.first().map(|data| &data.capture_history)
+    }
+}
+
 pub struct Engine<H> {
     pos: Position<'static>,
     main_handler: H,
     shared: SearchSharedState,
+    search_data: Vec<SearchData>,
+    best_move: Arc<BestMoveCell>,
     options: EngineOptions
 }
 
+/// Pin the current thread to a core, if the `affinity` feature is enabled.
+#[cfg(feature = "affinity")]
+fn pin_current_thread(core_index: usize) {
+    if let Some(core_ids) = core_affinity::get_core_ids() {
+        if let Some(&core_id) = core_ids.get(core_index % core_ids.len().max(1)) {
+            core_affinity::set_for_current(core_id);
+        }
+    }
+}
+
+#[cfg(not(feature = "affinity"))]
+fn pin_current_thread(_core_index: usize) {}
+
 impl<H: SearchHandler> Engine<H> {
     pub fn new(
         handler: H,
@@ -91,11 +194,37 @@ impl<H: SearchHandler> Engine<H> {
         search_params: SearchParams,
         cache_table: CacheTable
     ) -> Self {
-        let mut history = Vec::with_capacity(options.max_depth.get() as usize);
-        let mut board = init_pos;
-        for mv in moves {
-            history.push(board.hash());
-            board.play_unchecked(mv);
+        Self::new_with_memory(
+            handler,
+            init_pos,
+            moves,
+            options,
+            search_params,
+            cache_table,
+            SearchMemory::default()
+        )
+    }
+
+    /// Create an engine that carries over what a previous engine learned, from [`Engine::into_parts`].
+    /// History heuristics are aged rather than cleared, so later moves of the same game benefit.
+    pub fn new_with_memory(
+        handler: H,
+        init_pos: Board,
+        moves: impl IntoIterator<Item=Move>,
+        options: EngineOptions,
+        search_params: SearchParams,
+        cache_table: CacheTable,
+        memory: SearchMemory
+    ) -> Self {
+        let (board, history) = Self::play_moves(init_pos, moves, &options);
+        let threads = options.threads.get();
+        let mut search_data = memory.search_data;
+        search_data.truncate(threads as usize);
+        for search_data in &mut search_data {
+            search_data.reset(history.clone());
+        }
+        for thread_index in search_data.len() as u32..threads {
+            search_data.push(SearchData::new(history.clone(), thread_index));
         }
 
         Self {
@@ -105,71 +234,184 @@ impl<H: SearchHandler> Engine<H> {
                 history,
                 cache_table,
                 search_params: SearchParamHandler::new(search_params),
+                root_noise: options.root_noise.clone(),
+                break_root_ties: options.break_root_ties
             },
+            search_data,
+            best_move: Arc::new(BestMoveCell::new()),
             options
         }
     }
 
+    fn play_moves(
+        init_pos: Board,
+        moves: impl IntoIterator<Item=Move>,
+        options: &EngineOptions
+    ) -> (Board, Vec<u64>) {
+        let mut history = Vec::with_capacity(options.max_depth.get() as usize);
+        let mut board = init_pos;
+        for mv in moves {
+            history.push(board.hash());
+            board.play_unchecked(mv);
+        }
+        (board, history)
+    }
+
+    /// Set up a new position to search, reusing the allocated search data.
+    /// History heuristics are aged rather than cleared, so searches of related positions benefit.
+    pub fn set_position(&mut self, init_pos: Board, moves: impl IntoIterator<Item=Move>) {
+        let (board, history) = Self::play_moves(init_pos, moves, &self.options);
+        for search_data in &mut self.search_data {
+            search_data.reset(history.clone());
+        }
+        self.pos = Position::new(&Nnue::DEFAULT, board);
+        self.shared.history = history;
+    }
+
+    /// The best move found so far by the running search, without stopping it.
+    /// See [`Engine::best_move_cell`] for reading it from another thread.
+    pub fn best_move_now(&self) -> Option<Move> {
+        self.best_move.get()
+    }
+
+    /// A handle to the best move found so far, for polling from another thread during [`Engine::search`].
+    /// It's updated as the main thread finds better root moves, so it may come from an unfinished
+    /// iteration and differ from the move the search would settle on if stopped.
+    /// It's cleared at the start of each search.
+    pub fn best_move_cell(&self) -> Arc<BestMoveCell> {
+        self.best_move.clone()
+    }
+
+    /// Deliver an immediate result for a position where the game is already over
+    /// or that is a dead draw, rather than searching it.
+    fn search_terminal(&mut self) -> bool {
+        let board = self.pos.board();
+        let status = board.status();
+        let dead_draw = oracle::oracle(board) == Some(Eval::DRAW);
+        if status == GameStatus::Ongoing && !dead_draw {
+            return false;
+        }
+        let mut mv = None;
+        board.generate_moves(|moves| {
+            mv = moves.into_iter().next();
+            mv.is_some()
+        });
+        self.best_move.set(mv);
+        let eval = if status == GameStatus::Won {
+            Eval::mated_in(0)
+        } else {
+            Eval::DRAW
+        };
+        self.main_handler.new_result(SearchResult {
+            mv,
+            eval,
+            bound: CacheDataKind::Exact,
+            nodes: 0,
+            depth: 0,
+            seldepth: 0,
+            cache_approx_size_permill: self.shared.cache_table.approx_size_permill(),
+            principal_variation: mv.into_iter().collect()
+        });
+        true
+    }
+
     pub fn search(&mut self) {
+        self.best_move.set(None);
+        if self.search_terminal() {
+            return;
+        }
         let mut prev_eval = None;
 
-        let mut search_data = (0..self.options.threads.get())
-            .map(|_| SearchData::new(self.shared.history.clone()))
-            .collect::<Vec<_>>();
-
+        let search_data = &mut self.search_data;
         for depth in 1..=self.options.max_depth.get() {
             // CITE: Lazy SMP.
             // https://www.chessprogramming.org/Lazy_SMP
-            let terminate_workers = AtomicBool::new(false);
-            let result: Result<_, ()> = std::thread::scope(|scope| {
-                let (main_data, worker_data) = search_data.split_first_mut().unwrap();
-
-                let mut worker_handles = Vec::with_capacity(worker_data.len());
-                for search_data in worker_data {
-                    let mut handler = WorkerHandler {
-                        terminate: &terminate_workers
-                    };
-                    let shared = &self.shared;
-                    let pos = &self.pos;
-                    worker_handles.push(scope.spawn(move || {
-                        Searcher::search(
-                            &mut handler,
-                            shared,
-                            search_data,
-                            pos,
-                            depth,
-                            true,
-                            prev_eval
-                        )
-                    }));
-                }
-
-                let (result, mut stats) = Searcher::search(
+            let (result, stats) = if search_data.len() == 1 {
+                // Don't touch threads at all if there's only one, so this works without them.
+                Searcher::search(
                     &mut self.main_handler,
                     &self.shared,
-                    main_data,
+                    &mut search_data[0],
                     &self.pos,
                     depth,
                     depth > 1,
-                    prev_eval
-                );
-                terminate_workers.store(true, Ordering::Release);
-
-                let result = result?;
-                for handle in worker_handles {
-                    let (_, worker_stats) = handle.join().unwrap();
-                    stats.nodes += worker_stats.nodes;
-                    stats.seldepth = stats.seldepth.max(worker_stats.seldepth);
-                }
+                    prev_eval,
+                    Some(&*self.best_move)
+                )
+            } else {
+                let terminate_workers = AtomicBool::new(false);
+                std::thread::scope(|scope| {
+                    let (main_data, worker_data) = search_data.split_first_mut().unwrap();
+
+                    let mut worker_handles = Vec::with_capacity(worker_data.len());
+                    for (worker_index, search_data) in worker_data.iter_mut().enumerate() {
+                        let mut handler = WorkerHandler {
+                            terminate: &terminate_workers
+                        };
+                        let shared = &self.shared;
+                        let pos = &self.pos;
+                        let pin_thread = self.options.pin_threads;
+                        worker_handles.push(scope.spawn(move || {
+                            if pin_thread {
+                                // The main thread is left on core zero.
+                                pin_current_thread(worker_index + 1);
+                            }
+                            Searcher::search(
+                                &mut handler,
+                                shared,
+                                search_data,
+                                pos,
+                                depth,
+                                true,
+                                prev_eval,
+                                None
+                            )
+                        }));
+                    }
+
+                    let (result, mut stats) = Searcher::search(
+                        &mut self.main_handler,
+                        &self.shared,
+                        main_data,
+                        &self.pos,
+                        depth,
+                        depth > 1,
+                        prev_eval,
+                        Some(&*self.best_move)
+                    );
+                    terminate_workers.store(true, Ordering::Release);
 
-                Ok((result, stats))
-            });
-            let (SearcherResult { mv, eval }, stats) = match result {
+                    for handle in worker_handles {
+                        let (_, worker_stats) = handle.join().unwrap();
+                        stats.nodes += worker_stats.nodes;
+                        stats.seldepth = stats.seldepth.max(worker_stats.seldepth);
+                    }
+
+                    (result, stats)
+                })
+            };
+            let SearcherResult { mv, eval, bound } = match result {
                 Ok(result) => result,
-                Err(_) => break
+                Err(partial) => {
+                    // Even if the first iteration didn't finish, report the best move found in it.
+                    if let (None, Some(partial)) = (prev_eval, partial) {
+                        self.main_handler.new_result(SearchResult {
+                            mv: Some(partial.mv),
+                            eval: partial.eval,
+                            bound: partial.bound,
+                            nodes: stats.nodes,
+                            depth,
+                            seldepth: stats.seldepth,
+                            cache_approx_size_permill: self.shared.cache_table.approx_size_permill(),
+                            principal_variation: vec![partial.mv]
+                        });
+                    }
+                    break;
+                }
             };
 
             prev_eval = Some(eval);
+            self.best_move.set(Some(mv));
             let mut principal_variation = Vec::new();
             let mut history = self.shared.history.clone();
             let mut board = self.pos.board().clone();
@@ -177,31 +419,39 @@ impl<H: SearchHandler> Engine<H> {
                 history.push(board.hash());
                 board.play_unchecked(entry.best_move);
                 principal_variation.push(entry.best_move);
-                let repetitions = history.iter()
-                    .rev()
-                    .take(board.halfmove_clock() as usize + 1)
-                    .step_by(2) // Every second ply so it's our turn
-                    .skip(1)
-                    .filter(|&&hash| hash == board.hash())
-                    .count();
-                if repetitions > 2 || board.status() != GameStatus::Ongoing {
+                // Stop at threefold repetition, where the game would end.
+                if count_repetitions(&history, &board) >= 2 || board.status() != GameStatus::Ongoing {
                     break;
                 }
             }
 
-            self.main_handler.new_result(SearchResult {
-                mv,
+            let result = SearchResult {
+                mv: Some(mv),
                 eval,
+                bound,
                 nodes: stats.nodes,
                 depth,
                 seldepth: stats.seldepth,
                 cache_approx_size_permill: self.shared.cache_table.approx_size_permill(),
                 principal_variation
-            });
+            };
+            let continue_deepening = self.main_handler.continue_deepening(&result);
+            self.main_handler.new_result(result);
+            if !continue_deepening {
+                break;
+            }
         }
     }
 
     pub fn into_cache_table(self) -> CacheTable {
         self.shared.cache_table
     }
+
+    /// Take back the cache table and what the search learned, to carry over to the next search.
+    pub fn into_parts(self) -> (CacheTable, SearchMemory) {
+        let memory = SearchMemory {
+            search_data: self.search_data
+        };
+        (self.shared.cache_table, memory)
+    }
 }
@@ -9,7 +9,7 @@ mod partition;
 use see::*;
 use partition::*;
 
-pub use see::SeeScore;
+pub use see::{SeeScore, static_exchange_evaluation};
 
 // CITE: Move ordering.
 // This move ordering was originally derived from this page:
@@ -18,7 +18,8 @@ pub use see::SeeScore;
 pub enum MoveScore {
     UnderPromotion,
     LosingCapture(SeeScore, i32),
-    Quiet(i32),
+    /// History, then a tie break that's only nonzero on helper threads.
+    Quiet(i32, u8),
     Killer,
     Capture(SeeScore, i32),
     Pv
@@ -53,6 +54,9 @@ fn swap_max_move_to_front(moves: &mut [ScoredMove]) -> Option<&ScoredMove> {
 // Promotions included.
 const MAX_CAPTURES: usize = 12 * 8 + 4 * 4;
 
+// The maximum number of legal moves in any reachable position.
+const MAX_MOVES: usize = 218;
+
 struct MoveListData<'b> {
     board: &'b Board,
     pv_move: Option<Move>,
@@ -181,7 +185,9 @@ impl<'b> MoveList<'b> {
                                 killers.push((mv, MoveScore::Killer));
                             } else if matches!(mv.promotion, None | Some(Piece::Queen)) {
                                 let history = searcher.data.quiet_history.get(self.data.board, mv);
-                                quiets.push((mv, MoveScore::Quiet(history)));
+                                let tie_break = searcher.data.rng.as_ref()
+                                    .map_or(0, |rng| rng.tie_break(self.data.board, mv));
+                                quiets.push((mv, MoveScore::Quiet(history, tie_break)));
                             } else {
                                 underpromos.push((mv, MoveScore::UnderPromotion));
                             }
@@ -223,28 +229,73 @@ impl<'b> MoveList<'b> {
 }
 
 pub struct QSearchMoveList {
-    move_list: ArrayVec<ScoredMove, MAX_CAPTURES>,
+    move_list: ArrayVec<ScoredMove, MAX_MOVES>,
     yielded: usize
 }
 
 impl QSearchMoveList {
-    pub fn new<H>(board: &Board, searcher: &Searcher<H>) -> Self {
+    /// Captures with non-negative SEE, or every evasion if the side to move is in check.
+    /// Captures that can't win more than `min_gain` are left out when not in check.
+    /// With `include_checks`, quiet checks with non-negative SEE are included too.
+    pub fn new<H>(
+        board: &Board,
+        searcher: &Searcher<H>,
+        min_gain: Option<SeeScore>,
+        include_checks: bool
+    ) -> Self {
         let mut move_list = ArrayVec::new();
 
+        let in_check = !board.checkers().is_empty();
         let their_pieces = board.colors(!board.side_to_move());
+        // En passant is the only capture onto an empty square.
+        let en_passant = board.en_passant().map_or(BitBoard::EMPTY, |file| {
+            let rank = match board.side_to_move() {
+                Color::White => Rank::Sixth,
+                Color::Black => Rank::Third
+            };
+            Square::new(file, rank).bitboard()
+        });
         board.generate_moves(|moves| {
+            let mut capture_squares = their_pieces;
+            if moves.piece == Piece::Pawn {
+                capture_squares |= en_passant;
+            }
             let mut capture_moves = moves;
-            capture_moves.to &= their_pieces;
+            capture_moves.to &= capture_squares;
             for mv in capture_moves {
+                if !in_check && min_gain.map_or(false, |min_gain| max_capture_gain(board, mv) <= min_gain) {
+                    continue;
+                }
                 // CITE: This use of SEE in quiescence and pruning moves with
                 // negative SEE was implemented based on a chesspgoramming.org page.
                 // https://www.chessprogramming.org/Quiescence_Search#Limiting_Quiescence
                 let eval = static_exchange_evaluation(board, mv);
-                if eval < 0 {
-                    continue;
-                }
                 let history = searcher.data.capture_history.get(board, mv);
-                move_list.push((mv, MoveScore::Capture(eval, history)));
+                if eval >= 0 {
+                    move_list.push((mv, MoveScore::Capture(eval, history)));
+                } else if in_check {
+                    move_list.push((mv, MoveScore::LosingCapture(eval, history)));
+                }
+            }
+            if in_check || include_checks {
+                let mut quiet_moves = moves;
+                quiet_moves.to &= !capture_squares;
+                for mv in quiet_moves {
+                    if !in_check {
+                        if static_exchange_evaluation(board, mv) < 0 {
+                            continue;
+                        }
+                        let mut child = board.clone();
+                        child.play_unchecked(mv);
+                        if child.checkers().is_empty() {
+                            continue;
+                        }
+                    }
+                    let history = searcher.data.quiet_history.get(board, mv);
+                    let tie_break = searcher.data.rng.as_ref()
+                        .map_or(0, |rng| rng.tie_break(board, mv));
+                    move_list.push((mv, MoveScore::Quiet(history, tie_break)));
+                }
             }
             false
         });
@@ -15,17 +15,51 @@ fn piece_value(piece: Piece) -> SeeScore {
     }
 }
 
+/// The square of the pawn captured by an en passant move, or `None` if the move isn't en passant.
+fn en_passant_victim(board: &Board, mv: Move) -> Option<Square> {
+    let is_en_passant = board.piece_on(mv.from) == Some(Piece::Pawn)
+        && board.piece_on(mv.to).is_none()
+        && mv.from.file() != mv.to.file();
+    // The captured pawn is beside the capturing pawn, not on the target square.
+    is_en_passant.then(|| Square::new(mv.to.file(), mv.from.rank()))
+}
+
+/// The most material a capture can win if it isn't recaptured.
+pub fn max_capture_gain(board: &Board, capture: Move) -> SeeScore {
+    let victim = if en_passant_victim(board, capture).is_some() {
+        Some(Piece::Pawn)
+    } else {
+        board.piece_on(capture.to)
+    };
+    let mut gain = victim.map_or(0, piece_value);
+    if let Some(promotion) = capture.promotion {
+        gain += piece_value(promotion) - piece_value(Piece::Pawn);
+    }
+    gain
+}
+
 // CITE: Static exchange evaluation.
 // https://www.chessprogramming.org/Static_Exchange_Evaluation
+/// Evaluate the material gained or lost by the exchange on the target square of a move.
+/// Quiet moves are treated as capturing nothing, so moving to an attacked square scores negatively.
+/// Castling never exchanges anything and always scores zero.
 pub fn static_exchange_evaluation(board: &Board, capture: Move) -> SeeScore {
     use Piece::*;
 
     let target_sq = capture.to;
-    let initial_capture = board.piece_on(target_sq).unwrap();
     let initial_color = board.side_to_move();
+    // Castling is encoded as the king capturing its own rook.
+    if board.colors(initial_color).has(target_sq) {
+        return 0;
+    }
+    let mut initial_capture = board.piece_on(target_sq);
 
     // Attacker moved to target square, so remove it
     let mut blockers = board.occupied() ^ capture.from.bitboard();
+    if let Some(victim_sq) = en_passant_victim(board, capture) {
+        blockers ^= victim_sq.bitboard();
+        initial_capture = Some(Pawn);
+    }
     let mut attackers =
         get_king_moves(target_sq) & blockers                 & board.pieces(King) |
         get_knight_moves(target_sq) & blockers               & board.pieces(Knight) |
@@ -39,7 +73,14 @@ pub fn static_exchange_evaluation(board: &Board, capture: Move) -> SeeScore {
     let mut color = !initial_color;
 
     let mut gains = ArrayVec::<_, 32>::new();
-    gains.push(piece_value(initial_capture));
+    let mut initial_gain = initial_capture.map_or(0, piece_value);
+    if let Some(promotion) = capture.promotion {
+        initial_gain += piece_value(promotion) - piece_value(Pawn);
+        target_piece = promotion;
+    }
+    gains.push(initial_gain);
+    // Any pawn recapturing on the back rank also promotes. Assume it's to a queen.
+    let promotes = matches!(target_sq.rank(), Rank::First | Rank::Eighth);
 
     'exchange: loop {
         // Find least valuable piece to capture victim
@@ -47,8 +88,13 @@ pub fn static_exchange_evaluation(board: &Board, capture: Move) -> SeeScore {
             let our_attackers = attackers & board.colored_pieces(color, attacker_piece);
             if let Some(attacker_sq) = our_attackers.next_square() {                
                 // "Capture" victim
-                let victim_value = piece_value(target_piece);
-                gains.push(victim_value);
+                let mut gain = piece_value(target_piece);
+                let mut new_target_piece = attacker_piece;
+                if attacker_piece == Pawn && promotes {
+                    gain += piece_value(Queen) - piece_value(Pawn);
+                    new_target_piece = Queen;
+                }
+                gains.push(gain);
 
                 // We captured the king lol
                 if target_piece == Piece::King {
@@ -58,7 +104,7 @@ pub fn static_exchange_evaluation(board: &Board, capture: Move) -> SeeScore {
                 // "Move" attacker to target square
                 blockers ^= attacker_sq.bitboard();
                 attackers ^= attacker_sq.bitboard();
-                target_piece = attacker_piece;
+                target_piece = new_target_piece;
 
                 // Add new exposed sliding pieces
                 if matches!(attacker_piece, Rook | Queen) {
@@ -94,3 +140,63 @@ pub fn static_exchange_evaluation(board: &Board, capture: Move) -> SeeScore {
         return gains.pop().unwrap();
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn board_and_move(fen: &str, mv: &str) -> (Board, Move) {
+        let board = Board::from_fen(fen, false).unwrap();
+        let mv = mv.parse().unwrap();
+        assert!(board.is_legal(mv), "{} isn't legal in {}", mv, fen);
+        (board, mv)
+    }
+
+    fn see(fen: &str, mv: &str) -> SeeScore {
+        let (board, mv) = board_and_move(fen, mv);
+        static_exchange_evaluation(&board, mv)
+    }
+
+    #[test]
+    fn defended_pawn() {
+        assert_eq!(see("4k3/8/2p5/3p4/8/4N3/8/4K3 w - - 0 1", "e3d5"), -220);
+    }
+
+    #[test]
+    fn undefended_piece() {
+        assert_eq!(see("4k3/8/8/3n4/8/8/8/3RK3 w - - 0 1", "d1d5"), 320);
+    }
+
+    #[test]
+    fn x_ray_recapture() {
+        // The rook on d1 recaptures through the square the first rook left.
+        assert_eq!(see("3rk3/8/8/3p4/8/8/3R4/3RK3 w - - 0 1", "d2d5"), 100);
+    }
+
+    #[test]
+    fn en_passant() {
+        assert_eq!(see("4k3/8/8/3pP3/8/8/8/4K3 w - d6 0 1", "e5d6"), 100);
+        assert_eq!(see("4k3/2p5/8/3pP3/8/8/8/4K3 w - d6 0 1", "e5d6"), 0);
+        // The captured pawn no longer blocks the rook behind it.
+        assert_eq!(see("4k3/2p5/8/3pP3/8/8/8/3RK3 w - d6 0 1", "e5d6"), 100);
+    }
+
+    #[test]
+    fn capture_promotion() {
+        assert_eq!(see("r3k3/1P6/8/8/8/8/8/4K3 w - - 0 1", "b7a8q"), 1300);
+        // The promoted piece is what gets recaptured.
+        assert_eq!(see("r3k3/1P6/1n6/8/8/8/8/4K3 w - - 0 1", "b7a8q"), 400);
+    }
+
+    #[test]
+    fn quiet_promotion() {
+        assert_eq!(see("4k3/1P6/8/8/8/8/8/4K3 w - - 0 1", "b7b8q"), 800);
+        assert_eq!(see("4k3/1P1n4/8/8/8/8/8/4K3 w - - 0 1", "b7b8q"), -100);
+    }
+
+    #[test]
+    fn en_passant_capture_gain() {
+        let (board, mv) = board_and_move("4k3/8/8/3pP3/8/8/8/4K3 w - d6 0 1", "e5d6");
+        assert_eq!(max_capture_gain(&board, mv), piece_value(Piece::Pawn));
+    }
+}
@@ -2,6 +2,7 @@ use crate::eval::Eval;
 
 use super::window::Window;
 use super::moves::SeeScore;
+use super::search::{Depth, ONE_PLY};
 
 macro_rules! define_params {
     ($($name:ident = $params_name:ident {
@@ -39,12 +40,31 @@ define_params! {
         div_reduction_divisor: u8 = 4;
         margin_div: i32 = 90;
         margin_max_reduction: u8 = 2;
+        // Untuned. engine/tests/zugzwang.rs checks that verification at this depth finds zugzwang mates.
+        verify_min_depth: u8 = 12;
+        // Only a sliding piece is required by default. More is untested, so it's left for tuning.
+        min_non_pawn_material: u8 = 0;
     }
     lmp = LmpParams {
+        // The budget at depths 1 to 3.
         quiets_to_check: [usize; 3] = [7, 8, 17];
+        // Deeper than that, the budget is quadratic, up to `max_depth`.
+        // Pruning past depth 3 is untested, so it's left for tuning.
+        base: usize = 6;
+        factor: usize = 1;
+        max_depth: u8 = 3;
+        // Weigh the budget by quiet history. This is untested, so it's left for tuning.
+        history_weighted: bool = false;
+        bad_history: i32 = -256;
+        bad_history_cost: usize = 2;
+        good_history: i32 = 384;
     }
     fp = FpParams {
-        margins: [i16; 2] = [293, 620];
+        // Margins of 293 and 620 at depths 1 and 2, as before they were linear.
+        // Pruning deeper is untested, so it's left for tuning.
+        base_margin: i16 = -34;
+        per_depth_margin: i16 = 327;
+        max_depth: u8 = 2;
     }
     rfp = RfpParams {
         base_margin: i16 = 30;
@@ -57,6 +77,43 @@ define_params! {
     see = SeeParams {
         losing_capture_reduction_threshold: SeeScore = -200;
         losing_capture_reduction: u8 = 2;
+        // Reducing captures that lose less than the threshold is untested, so it's left for tuning.
+        slightly_losing_capture_reduction: u8 = 0;
+        history_reduction_div: i32 = 256;
+    }
+    qsearch = QSearchParams {
+        // Delta pruning is untested, so it's off unless tuned.
+        delta_pruning: bool = false;
+        delta_margin: i16 = 200;
+        max_ply: u8 = u8::MAX;
+        // Untuned, so off. engine/tests/quiescence.rs checks that quiet checks find mates when on.
+        check_plies: u8 = 0;
+    }
+    history = HistoryParams {
+        gravity: bool = false;
+    }
+    aspiration = AspirationParams {
+        initial_delta: i16 = 25;
+        widen_factor: i16 = 2;
+        max_researches: u8 = 4;
+    }
+}
+
+impl SearchParams {
+    /// Faster but slightly weaker params for generating training data,
+    /// where many quick games are worth more than the best play in each.
+    /// The default params are the strongest. Compared to them, this prunes more aggressively:
+    /// - Late move pruning applies up to a higher depth.
+    /// - Futility and reverse futility pruning apply up to a higher depth.
+    /// - Null move pruning reduces more.
+    pub fn datagen() -> Self {
+        let mut params = Self::default();
+        params.lmp.base = 4;
+        params.lmp.max_depth = 14;
+        params.fp.max_depth = 8;
+        params.rfp.max_depth = 6;
+        params.nmp.base_reduction = 4;
+        params
     }
 }
 
@@ -82,7 +139,7 @@ impl<T: Copy + Default, const I: usize, const J: usize> Lut2d<T, I, J> {
 
 pub struct SearchParamHandler {
     params: SearchParams,
-    lmr_lut: Lut2d<u8, 64, 64>,
+    lmr_lut: Lut2d<Depth, 64, 64>,
 }
 
 impl SearchParamHandler {
@@ -90,7 +147,9 @@ impl SearchParamHandler {
         let lmr_lut = Lut2d::new(|depth, move_index| {
             let base = params.lmr.base_reduction;
             let div = params.lmr.div;
-            (base + (depth as f32).ln() * (move_index as f32).ln() / div) as u8
+            let reduction = base + (depth as f32).ln() * (move_index as f32).ln() / div;
+            // Whole plies for now. Fractional reductions would change the defaults without tuning.
+            (reduction as Depth) * ONE_PLY
         });
         Self { params, lmr_lut }
     }
@@ -99,10 +158,11 @@ impl SearchParamHandler {
         self.params.lmr.min_depth
     }
 
-    pub fn lmr_reduction(&self, move_index: usize, depth: u8, history: i32) -> u8 {
+    /// The reduction in fractions of a ply. It's always a whole number of plies.
+    pub fn lmr_reduction(&self, move_index: usize, depth: u8, history: i32) -> Depth {
         let mut reduction = self.lmr_lut.get(depth as usize, move_index) as i32;
-        reduction -= history / self.params.lmr.history_reduction_div;
-        reduction.max(0) as u8
+        reduction -= history / self.params.lmr.history_reduction_div * ONE_PLY as i32;
+        reduction.max(0) as Depth
     }
 
     pub fn nmp_reduction(&self, depth: u8, static_eval: Eval, window: Window) -> u8 {
@@ -119,14 +179,53 @@ impl SearchParamHandler {
         reduction
     }
 
+    /// The depth from which null move cutoffs are verified by a reduced search without null moves.
+    /// The default hasn't been tuned yet; it's exposed as `TUNE_nmp.verify_min_depth` for that.
+    pub fn nmp_verify_min_depth(&self) -> u8 {
+        self.params.nmp.verify_min_depth
+    }
+
+    /// The non-pawn material, in pawns, the side to move needs for null move pruning,
+    /// so that it's skipped in endgames likely to be zugzwang.
+    pub fn nmp_min_non_pawn_material(&self) -> u8 {
+        self.params.nmp.min_non_pawn_material
+    }
+
+    /// The late move pruning budget. It comes from a table at low depths,
+    /// and grows quadratically with depth past the table.
     pub fn lmp_quiets_to_check(&self, depth: u8) -> usize {
-        *self.params.lmp.quiets_to_check.get(depth as usize - 1)
-            .unwrap_or(&usize::MAX)
+        let lmp = &self.params.lmp;
+        if depth > lmp.max_depth {
+            return usize::MAX;
+        }
+        match lmp.quiets_to_check.get(depth as usize - 1) {
+            Some(&quiets) => quiets,
+            None => lmp.base + lmp.factor * depth as usize * depth as usize
+        }
+    }
+
+    /// How much of the late move pruning budget a quiet with some history uses.
+    /// If it's weighted by history, quiets with good enough history are never pruned.
+    pub fn lmp_quiet_cost(&self, history: i32) -> Option<usize> {
+        let lmp = &self.params.lmp;
+        if !lmp.history_weighted {
+            Some(1)
+        } else if history >= lmp.good_history {
+            None
+        } else if history <= lmp.bad_history {
+            Some(lmp.bad_history_cost)
+        } else {
+            Some(1)
+        }
     }
 
     pub fn fp_margin(&self, depth: u8) -> Option<Eval> {
-        self.params.fp.margins.get(depth as usize - 1)
-            .map(|&e| Eval::cp(e))
+        let fp = &self.params.fp;
+        if depth <= fp.max_depth {
+            Some(Eval::cp(fp.base_margin + fp.per_depth_margin * depth as i16))
+        } else {
+            None
+        }
     }
 
     pub fn rfp_margin(&self, depth: u8) -> Option<Eval> {
@@ -146,10 +245,93 @@ impl SearchParamHandler {
         }
     }
 
-    pub fn see_reduction(&self, score: SeeScore) -> u8 {
-        if score <= self.params.see.losing_capture_reduction_threshold {
-            return self.params.see.losing_capture_reduction;
+    /// The margin for delta pruning in quiescence search, on top of the material a capture wins.
+    /// `None` if delta pruning is off.
+    pub fn qsearch_delta_margin(&self) -> Option<i16> {
+        let qsearch = &self.params.qsearch;
+        qsearch.delta_pruning.then(|| qsearch.delta_margin)
+    }
+
+    /// How many plies quiescence search goes before falling back to the static eval.
+    /// The default is high enough that this never happens.
+    pub fn qsearch_max_ply(&self) -> u8 {
+        self.params.qsearch.max_ply
+    }
+
+    /// Whether quiescence search tries quiet checks at some ply.
+    /// Only the first few plies do, so the search doesn't blow up.
+    pub fn qsearch_include_checks(&self, qsearch_ply: u8) -> bool {
+        qsearch_ply < self.params.qsearch.check_plies
+    }
+
+    /// Whether history updates use the gravity formula, which approaches the limit smoothly.
+    pub fn history_gravity(&self) -> bool {
+        self.params.history.gravity
+    }
+
+    /// At least one centipawn, since a zero delta would make an empty window.
+    pub fn aspiration_initial_delta(&self) -> Eval {
+        Eval::cp(self.params.aspiration.initial_delta.max(1))
+    }
+
+    /// Widen a failed aspiration window, or give up on it if it's been widened too many times.
+    pub fn aspiration_widen(&self, delta: Eval, researches: u8) -> Option<Eval> {
+        let aspiration = &self.params.aspiration;
+        if researches < aspiration.max_researches {
+            Some(delta.saturating_mul(Eval::cp(aspiration.widen_factor)).max(Eval::UNIT))
+        } else {
+            None
+        }
+    }
+
+    pub fn see_reduction(&self, score: SeeScore, history: i32) -> u8 {
+        let see = &self.params.see;
+        let mut reduction = if score <= see.losing_capture_reduction_threshold {
+            see.losing_capture_reduction
+        } else {
+            see.slightly_losing_capture_reduction
+        } as i32;
+        reduction -= history / see.history_reduction_div;
+        reduction.max(0) as u8
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn lmr_reductions() {
+        let params = SearchParamHandler::new(SearchParams::default());
+        // (depth, move index, reduction in plies)
+        let expected = [
+            (1, 1, 0),
+            (1, 40, 0),
+            (3, 3, 0),
+            (8, 8, 1),
+            // 1.97 plies, which rounds down.
+            (5, 30, 1),
+            (10, 20, 2),
+            (63, 63, 6)
+        ];
+        for (depth, move_index, plies) in expected {
+            assert_eq!(
+                params.lmr_reduction(move_index, depth, 0),
+                plies * ONE_PLY,
+                "depth {} move {}",
+                depth,
+                move_index
+            );
         }
-        0
+    }
+
+    #[test]
+    fn lmr_history_reductions() {
+        let params = SearchParamHandler::new(SearchParams::default());
+        let div = SearchParams::default().lmr.history_reduction_div;
+        assert_eq!(params.lmr_reduction(20, 10, div - 1), 2 * ONE_PLY);
+        assert_eq!(params.lmr_reduction(20, 10, div), ONE_PLY);
+        assert_eq!(params.lmr_reduction(20, 10, -div), 3 * ONE_PLY);
+        assert_eq!(params.lmr_reduction(20, 10, 10 * div), 0);
     }
 }
@@ -3,14 +3,23 @@ use cozy_chess::*;
 
 use crate::eval::*;
 use super::position::Position;
-use super::{SearchHandler, SearchParamHandler};
+use super::{SearchHandler, SearchResult, SearchParamHandler, RootNoise, BestMoveCell};
 use super::cache::*;
-use super::helpers::{move_is_quiet, move_is_capture};
+use super::helpers::{move_is_quiet, move_is_capture, move_order_key, is_repetition_draw, SearchRng};
 use super::moves::*;
 use super::window::Window;
 use super::oracle;
 use super::history::HistoryTable;
 
+/// Depth in fractions of a ply, so extensions and reductions can be finer than a whole ply.
+pub type Depth = u16;
+
+pub const ONE_PLY: Depth = 4;
+
+fn whole_plies(depth: Depth) -> u8 {
+    (depth / ONE_PLY) as u8
+}
+
 #[derive(Debug, Clone, Default)]
 pub struct SearchStats {
     pub nodes: u64,
@@ -20,7 +29,8 @@ pub struct SearchStats {
 #[derive(Debug, Clone)]
 pub struct SearcherResult {
     pub mv: Move,
-    pub eval: Eval
+    pub eval: Eval,
+    pub bound: CacheDataKind
 }
 
 /// Represents shared data required by all search threads.
@@ -28,30 +38,58 @@ pub struct SearchSharedState {
     pub history: Vec<u64>,
     pub cache_table: CacheTable,
     pub search_params: SearchParamHandler,
+    pub root_noise: Option<RootNoise>,
+    pub break_root_ties: bool
 }
 
 pub const KILLER_ENTRIES: usize = 2;
+
+/// The deepest ply the search will reach, counting extensions and quiescence search.
+/// Nodes at this ply return their static evaluation instead of searching further.
+///
+/// Plies are a `u8` rather than anything wider because mate scores only encode distances
+/// up to [`u8::MAX`] plies (see [`Eval::mate_in`]), so deeper nodes couldn't be scored properly anyway.
+/// In practice the cap is almost never reached: iterative deepening stops at depth 255,
+/// in-tree repetitions end check extension cycles, and quiescence search runs out of
+/// captures long before. Hitting the cap only costs accuracy at that one node.
+pub const MAX_PLY: u8 = u8::MAX - 1;
+
+/// The number of nodes between calls to [`SearchHandler::progress`].
+const PROGRESS_INTERVAL: u64 = 1 << 16;
 pub(crate) type KillerEntry = ArrayVec<Move, KILLER_ENTRIES>;
 
 /// Represents the local data required to start one search.
 /// This struct is also reused between iterations.
 pub struct SearchData {
     pub game_history: Vec<u64>,
-    pub killers: [KillerEntry; u8::MAX as usize],
+    pub killers: [KillerEntry; MAX_PLY as usize + 1],
     pub quiet_history: HistoryTable,
-    pub capture_history: HistoryTable
+    pub capture_history: HistoryTable,
+    /// Breaks move ordering ties differently on each helper thread.
+    /// The main thread has none, so single threaded search is unaffected.
+    pub rng: Option<SearchRng>
 }
 
 impl SearchData {
-    pub fn new(history: Vec<u64>) -> Self {
+    pub fn new(history: Vec<u64>, thread_index: u32) -> Self {
         const EMPTY_KILLER_ENTRY: KillerEntry = KillerEntry::new_const();
         Self {
             game_history: history,
-            killers: [EMPTY_KILLER_ENTRY; u8::MAX as usize],
+            killers: [EMPTY_KILLER_ENTRY; MAX_PLY as usize + 1],
             quiet_history: HistoryTable::new(),
             capture_history: HistoryTable::new(),
+            rng: (thread_index > 0).then(|| SearchRng::new(thread_index as u64))
         }
     }
+
+    /// Prepare to search a new position, keeping (but aging) the learned history.
+    pub fn reset(&mut self, history: Vec<u64>) {
+        const EMPTY_KILLER_ENTRY: KillerEntry = KillerEntry::new_const();
+        self.game_history = history;
+        self.killers.fill(EMPTY_KILLER_ENTRY);
+        self.quiet_history.age();
+        self.capture_history.age();
+    }
 }
 
 /// Represents a single search at some point in time.
@@ -59,9 +97,14 @@ pub struct Searcher<'s, H> {
     handler: &'s mut H,
     shared: &'s SearchSharedState,
     pub data: &'s mut SearchData,
-    search_result: Option<Move>,
+    search_result: Option<(Move, Eval)>,
+    /// Where to publish the best root move as it changes, if anywhere.
+    best_move: Option<&'s BestMoveCell>,
     stats: SearchStats,
-    allow_abort: bool
+    allow_abort: bool,
+    nmp_min_ply: u8,
+    depth: u8,
+    next_progress: u64
 }
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
@@ -79,46 +122,94 @@ impl<H: SearchHandler> Searcher<'_, H> {
         pos: &Position,
         depth: u8,
         allow_abort: bool,
-        prev_eval: Option<Eval>
-    ) -> (Result<SearcherResult, ()>, SearchStats) {
+        prev_eval: Option<Eval>,
+        best_move: Option<&BestMoveCell>
+    ) -> (Result<SearcherResult, Option<SearcherResult>>, SearchStats) {
         let mut searcher = Searcher {
             handler,
             shared,
             data,
             search_result: None,
+            best_move,
             stats: SearchStats::default(),
-            allow_abort
+            allow_abort,
+            nmp_min_ply: 0,
+            depth,
+            next_progress: PROGRESS_INTERVAL
         };
 
-        let mut windows = [25].iter().copied().map(Eval::cp);
-        let eval = loop {
-            // CITE: Aspiration window.
-            // https://www.chessprogramming.org/Aspiration_Windows
-            let mut aspiration_window = Window::INFINITY;
-            if let Some(prev_eval) = prev_eval {
-                if prev_eval.as_cp().is_some() && depth > 3 {
-                    if let Some(bounds) = windows.next() {
-                        aspiration_window = Window::around(prev_eval, bounds);
-                    }
-                }
+        // CITE: Aspiration window.
+        // https://www.chessprogramming.org/Aspiration_Windows
+        let mut aspiration_window = Window::INFINITY;
+        let mut center = Eval::ZERO;
+        let mut delta = searcher.shared.search_params.aspiration_initial_delta();
+        let mut researches = 0;
+        if let Some(prev_eval) = prev_eval {
+            if prev_eval.as_cp().is_some() && depth > 3 {
+                center = prev_eval;
+                aspiration_window = Window::asymmetric(center, delta, delta);
             }
+        }
+        let eval = loop {
             let eval = searcher.search_node(
                 Node::Root,
                 pos,
-                depth,
+                depth as Depth * ONE_PLY,
                 0,
                 aspiration_window
             );
-            match eval {
+            let eval = match eval {
                 Ok(eval) if aspiration_window.contains(eval) => break Ok(eval),
-                Err(e) => break Err(e),
-                _ => continue
+                Ok(eval) => eval,
+                Err(e) => break Err(e)
+            };
+            if let Some((mv, _)) = searcher.search_result {
+                searcher.handler.window_failed(SearchResult {
+                    mv: Some(mv),
+                    eval,
+                    bound: aspiration_window.bound(eval),
+                    nodes: searcher.stats.nodes,
+                    depth,
+                    seldepth: searcher.stats.seldepth,
+                    cache_approx_size_permill: searcher.shared.cache_table.approx_size_permill(),
+                    principal_variation: vec![mv]
+                });
             }
+            // Only widen the side that failed. Once the widening budget
+            // is used up, that side is opened up fully instead.
+            let widened = searcher.shared.search_params.aspiration_widen(delta, researches);
+            researches = researches.saturating_add(1);
+            if let Some(widened) = widened {
+                delta = widened;
+            }
+            if eval <= aspiration_window.alpha {
+                aspiration_window.alpha = match widened {
+                    Some(delta) => center.saturating_sub(delta),
+                    None => Eval::MIN
+                };
+            } else {
+                aspiration_window.beta = match widened {
+                    Some(delta) => center.saturating_add(delta),
+                    None => Eval::MAX
+                };
+            }
+        };
+        let result = match eval {
+            // The loop only ends once the window contains the eval, so it's exact.
+            // The bounds from failed windows were reported as they happened.
+            Ok(eval) => Ok(SearcherResult {
+                mv: searcher.search_result.unwrap().0,
+                eval,
+                bound: CacheDataKind::Exact
+            }),
+            // The best root move so far, in case there's nothing better to fall back on.
+            // Not every root move was searched, so the best eval is only a lower bound.
+            Err(()) => Err(searcher.search_result.map(|(mv, eval)| SearcherResult {
+                mv,
+                eval,
+                bound: CacheDataKind::LowerBound
+            }))
         };
-        let result = eval.map(|eval| SearcherResult {
-            mv: searcher.search_result.unwrap(),
-            eval
-        });
         
         (result, searcher.stats)
     }
@@ -129,7 +220,7 @@ impl<H: SearchHandler> Searcher<'_, H> {
         &mut self,
         node: Node,
         pos: &Position,
-        mut depth: u8,
+        mut depth: Depth,
         ply_index: u8,
         mut window: Window
     ) -> Result<Eval, ()> {
@@ -143,44 +234,56 @@ impl<H: SearchHandler> Searcher<'_, H> {
             if in_check {
                 // CITE: Check extensions.
                 // https://www.chessprogramming.org/Check_Extensions
-                depth += 1;
+                depth += ONE_PLY;
             }
 
-            if depth == 0 {
-                if node != Node::Root && self.repetitions(pos.board()) > 1 {
-                    return Ok(Eval::DRAW);
-                }
+            // The root is exempt, as it has to find a move to play.
+            if node != Node::Root && self.is_repetition_draw(pos.board()) {
+                return Ok(Eval::DRAW);
+            }
+
+            if depth < ONE_PLY {
                 // We are allowed to search in this node as qsearch doesn't track history
-                return Ok(self.quiescence(pos, ply_index, window));
+                return Ok(self.quiescence(pos, ply_index, 0, window));
             }
 
             self.stats.nodes += 1;
 
-            let init_window = window;
-
-            if self.allow_abort && self.handler.stop_search(self.stats.nodes) {
+            // Without a previous iteration to fall back on, keep going until there's a root move.
+            let can_abort = self.allow_abort || self.search_result.is_some();
+            if can_abort && self.handler.stop_search(self.stats.nodes) {
                 return Err(());
             }
-
-            if node != Node::Root && self.repetitions(pos.board()) > 0 {
-                return Ok(Eval::DRAW);
+            if self.stats.nodes >= self.next_progress {
+                self.next_progress = self.stats.nodes + PROGRESS_INTERVAL;
+                self.handler.progress(self.stats.nodes, self.depth);
             }
+
             match pos.board().status() {
                 GameStatus::Won => return Ok(Eval::mated_in(ply_index)),
                 GameStatus::Drawn => return Ok(Eval::DRAW),
                 GameStatus::Ongoing => {}
             }
+            if ply_index >= MAX_PLY {
+                return Ok(pos.evaluate());
+            }
             if node != Node::Root {
                 if let Some(eval) = oracle::oracle(pos.board()) {
                     return Ok(eval);
                 }
+                // CITE: Mate distance pruning.
+                // https://www.chessprogramming.org/Mate_Distance_Pruning
+                window.narrow_to_mate_distance(ply_index);
+                if window.empty() {
+                    return Ok(window.alpha);
+                }
             }
 
             let mut pv_move = None;
             let cache_entry = self.shared.cache_table.get(pos.board(), ply_index);
             if let Some(entry) = cache_entry {
                 pv_move = Some(entry.best_move);
-                if !matches!(node, Node::Root | Node::Pv) && entry.depth >= depth {
+                if !matches!(node, Node::Root | Node::Pv) && entry.depth >= whole_plies(depth) {
                     match entry.kind {
                         CacheDataKind::Exact => return Ok(entry.eval),
                         CacheDataKind::LowerBound => window.narrow_alpha(entry.eval),
@@ -190,9 +293,20 @@ impl<H: SearchHandler> Searcher<'_, H> {
                         return Ok(entry.eval);
                     }
                 }
-            } else {
-                depth -= self.shared.search_params.iir_reduction(depth);
             }
+            // The search is fail-soft, so the best eval may fall outside the window.
+            // Whether it's a bound depends on where it falls relative to the window
+            // the moves were actually searched with, which is after any narrowing above.
+            let init_window = window;
+            if pv_move.is_none() {
+                // CITE: Internal iterative reductions.
+                // Without a TT move our move ordering is poor, so we search at a lower depth
+                // and rely on the next iteration to revisit this node with a TT move.
+                // https://www.chessprogramming.org/Internal_Iterative_Reductions
+                let reduction = self.shared.search_params.iir_reduction(whole_plies(depth));
+                depth -= reduction as Depth * ONE_PLY;
+            }
+            let plies = whole_plies(depth);
 
             let static_eval = cache_entry
                 .and_then(|e| {
@@ -207,7 +321,7 @@ impl<H: SearchHandler> Searcher<'_, H> {
             if !matches!(node, Node::Root | Node::Pv) {
                 // CITE: Reverse futility pruning.
                 // https://www.chessprogramming.org/Reverse_Futility_Pruning
-                if let Some(margin) = self.shared.search_params.rfp_margin(depth) {
+                if let Some(margin) = self.shared.search_params.rfp_margin(plies) {
                     let eval_estimate = static_eval.saturating_sub(margin);
                     if eval_estimate >= window.beta {
                         return Ok(eval_estimate);
@@ -220,32 +334,68 @@ impl<H: SearchHandler> Searcher<'_, H> {
                 pos.board().pieces(Piece::Rook) |
                 pos.board().pieces(Piece::Bishop) |
                 pos.board().pieces(Piece::Queen);
+            let non_pawn_material = |piece, value| {
+                (our_pieces & pos.board().pieces(piece)).len() as u8 * value
+            };
+            let our_non_pawn_material =
+                non_pawn_material(Piece::Knight, 3) +
+                non_pawn_material(Piece::Bishop, 3) +
+                non_pawn_material(Piece::Rook, 5) +
+                non_pawn_material(Piece::Queen, 9);
 
             let mut best_move = None;
             let mut best_eval = Eval::MIN;
+            // The best root move's eval with noise added, which decides the move to play.
+            let mut best_root_choice = Eval::MIN;
             // CITE: Null move pruning.
             // The idea for doing it only when static_eval >= beta was
             // first suggested to me by the Black Marlin author.
             // https://www.chessprogramming.org/Null_Move_Pruning
             let do_nmp = static_eval >= window.beta
-                && !(our_pieces & sliding_pieces).is_empty();
+                && !(our_pieces & sliding_pieces).is_empty()
+                && our_non_pawn_material >= self.shared.search_params.nmp_min_non_pawn_material()
+                && ply_index >= self.nmp_min_ply;
             if node != Node::Root && do_nmp {
                 if let Some(child) = pos.null_move() {
                     let mut window = window.null_window_beta();
-                    let reduction = self.shared.search_params.nmp_reduction(depth, static_eval, window);
+                    let reduction = self.shared.search_params.nmp_reduction(plies, static_eval, window);
+                    let null_depth = depth.saturating_sub(ONE_PLY + reduction as Depth * ONE_PLY);
                     let eval = -self.search_node(
                         Node::Normal,
                         &child,
-                        (depth - 1).saturating_sub(reduction),
+                        null_depth,
                         ply_index + 1,
                         -window
                     )?;
                     window.narrow_alpha(eval);
                     if window.empty() {
-                        //TODO This might not bet correct since we can return a false mate score.
-                        //Not quite sure what to do in that case though.
-                        //NMP operates on the assumption that some other move works too anyway though.
-                        return Ok(eval);
+                        //A mate found after passing relies on the opponent passing too,
+                        //which isn't a real mate. Only claim the bound in that case.
+                        let eval = if eval.as_cp().is_none() { window.beta } else { eval };
+                        if plies < self.shared.search_params.nmp_verify_min_depth() {
+                            return Ok(eval);
+                        }
+                        // CITE: Null move verification search.
+                        // At high depths, zugzwang could make the null move cutoff wrong, so we verify
+                        // it with a search of this node at the same depth with null moves disabled
+                        // for the first few plies. This is done the same way as in Stockfish.
+                        // https://www.chessprogramming.org/Null_Move_Pruning#Verification_Search
+                        let prev_nmp_min_ply = self.nmp_min_ply;
+                        self.nmp_min_ply = ply_index + whole_plies(null_depth) * 3 / 4;
+                        // Take this node off the history so it isn't seen as a repetition of itself.
+                        let hash = self.data.game_history.pop().unwrap();
+                        let verified = self.search_node(
+                            Node::Normal,
+                            pos,
+                            null_depth,
+                            ply_index,
+                            window.null_window_beta()
+                        );
+                        self.data.game_history.push(hash);
+                        self.nmp_min_ply = prev_nmp_min_ply;
+                        if verified? >= window.beta {
+                            return Ok(eval);
+                        }
                     }
                 }
             }
@@ -258,22 +408,26 @@ impl<H: SearchHandler> Searcher<'_, H> {
             // CITE: Futility pruning.
             // This implementation is also based on extended futility pruning.
             // https://www.chessprogramming.org/Futility_Pruning
-            let futile = if let Some(margin) = self.shared.search_params.fp_margin(depth) {
+            let futile = if let Some(margin) = self.shared.search_params.fp_margin(plies) {
                 let max_eval = static_eval.saturating_add(margin);
                 max_eval <= window.alpha
             } else {
                 false
             };
-            let mut quiets_to_check = self.shared.search_params.lmp_quiets_to_check(depth);
+            let mut quiets_to_check = self.shared.search_params.lmp_quiets_to_check(plies);
             while let Some((i, (mv, move_score))) = moves.pick(self) {
                 // CITE: Late move pruning.
                 // We check only a certain number of quiets per node given some depth.
                 // This was suggested to me by the Black Marlin author.
-                if let MoveScore::Quiet(_) = move_score {
-                    if quiets_to_check > 0 {
-                        quiets_to_check -= 1;
-                    } else {
-                        continue;
+                // Quiets with bad history use up more of the budget, and quiets with good history are kept.
+                // The PV move and killers are scored separately, so they're never pruned here.
+                if let MoveScore::Quiet(history, _) = move_score {
+                    if let Some(cost) = self.shared.search_params.lmp_quiet_cost(history) {
+                        if quiets_to_check >= cost {
+                            quiets_to_check -= cost;
+                        } else {
+                            continue;
+                        }
                     }
                 }
                 let child = pos.play_unchecked(mv);
@@ -299,17 +453,19 @@ impl<H: SearchHandler> Searcher<'_, H> {
                 let mut reduction = 0;
                 // CITE: Late move reductions.
                 // https://www.chessprogramming.org/Late_Move_Reductions
-                if depth >= self.shared.search_params.lmr_min_depth() && is_quiet && !in_check && !gives_check {
+                // The reduction is in fractions of a ply, so it accumulates finely over the line.
+                if plies >= self.shared.search_params.lmr_min_depth() && is_quiet && !in_check && !gives_check {
                     let history = self.data.quiet_history.get(pos.board(), mv);
-                    reduction += self.shared.search_params.lmr_reduction(i, depth, history);
+                    reduction += self.shared.search_params.lmr_reduction(i, plies, history);
                 }
-                if let MoveScore::LosingCapture(score, _) = move_score {
-                    reduction += self.shared.search_params.see_reduction(score);
+                // Losing captures are reduced too, but less so if they've been good before.
+                if let MoveScore::LosingCapture(score, history) = move_score {
+                    reduction += self.shared.search_params.see_reduction(score, history) as Depth * ONE_PLY;
                 }
                 let mut eval = -self.search_node(
                     child_node_type,
                     &child,
-                    (depth - 1).saturating_sub(reduction),
+                    depth.saturating_sub(ONE_PLY + reduction),
                     ply_index + 1,
                     -child_window
                 )?;
@@ -319,19 +475,70 @@ impl<H: SearchHandler> Searcher<'_, H> {
                     eval = -self.search_node(
                         child_node_type,
                         &child,
-                        depth - 1,
+                        depth - ONE_PLY,
                         ply_index + 1,
                         -child_window
                     )?;
                 }
 
-                if eval > best_eval {
+                let mut tie = false;
+                if node == Node::Root
+                    && self.shared.break_root_ties
+                    && eval == best_eval
+                    && best_eval > init_window.alpha
+                    && best_move.map_or(false, |best| move_order_key(mv) < move_order_key(best))
+                {
+                    // A move that evaluates equal to the best is usually only known to be no better,
+                    // so search it again with a window around the best eval to see if it really ties.
+                    let tie_window = Window::new(
+                        best_eval.saturating_sub(Eval::cp(1)),
+                        best_eval.saturating_add(Eval::cp(1))
+                    );
+                    eval = -self.search_node(
+                        Node::Pv,
+                        &child,
+                        depth - ONE_PLY,
+                        ply_index + 1,
+                        -tie_window
+                    )?;
+                    if eval >= tie_window.beta {
+                        // It's actually better, but failing high on the narrow window
+                        // only gives a lower bound, so get its real eval.
+                        eval = -self.search_node(
+                            Node::Pv,
+                            &child,
+                            depth - ONE_PLY,
+                            ply_index + 1,
+                            -window
+                        )?;
+                    }
+                    tie = eval == best_eval;
+                }
+
+                if eval > best_eval || tie {
                     best_eval = eval;
                     best_move = Some(mv);
                 }
+                if node == Node::Root {
+                    // Moves that fail low only have an upper bound as their eval, so
+                    // with noise this tends to keep the best move unless others are close.
+                    let choice = match &self.shared.root_noise {
+                        Some(noise) => eval.saturating_add(noise.noise(pos.board(), mv)),
+                        None => eval
+                    };
+                    if choice > best_root_choice || (tie && choice == best_root_choice) {
+                        best_root_choice = choice;
+                        // Keep track of the best move so far so an aborted search still has one.
+                        self.search_result = Some((mv, eval));
+                        if let Some(best_move) = self.best_move {
+                            best_move.set(Some(mv));
+                        }
+                    }
+                }
 
                 window.narrow_alpha(eval);
                 if window.empty() {
+                    let gravity = self.shared.search_params.history_gravity();
                     if is_quiet {
                         // CITE: Killer moves.
                         // https://www.chessprogramming.org/Killer_Heuristic
@@ -342,11 +549,11 @@ impl<H: SearchHandler> Searcher<'_, H> {
                         killers.push(mv);
                         // CITE: History heuristic.
                         // https://www.chessprogramming.org/History_Heuristic
-                        self.data.quiet_history.update(pos.board(), mv, depth, true);
+                        self.data.quiet_history.update(pos.board(), mv, plies, true, gravity);
                     }
                     if is_capture {
                         // CITE: Capture history.
-                        self.data.capture_history.update(pos.board(), mv, depth, true);
+                        self.data.capture_history.update(pos.board(), mv, plies, true, gravity);
                     }
                     // CITE: We additionally punish the history of quiet moves that don't produce cutoffs.
                     // Suggested by the Black Marlin author and additionally observed in MadChess.
@@ -354,10 +561,10 @@ impl<H: SearchHandler> Searcher<'_, H> {
                         if prev_mv != mv {
                             // Punish quiets only if the cutoff was not caused by a capture, which is expected.
                             if !is_capture && move_is_quiet(prev_mv, pos.board()) {
-                                self.data.quiet_history.update(pos.board(), prev_mv, depth, false);
+                                self.data.quiet_history.update(pos.board(), prev_mv, plies, false, gravity);
                             }
                             if move_is_capture(prev_mv, pos.board()) {
-                                self.data.capture_history.update(pos.board(), prev_mv, depth, false);
+                                self.data.capture_history.update(pos.board(), prev_mv, plies, false, gravity);
                             }
                         }
                     }
@@ -367,25 +574,12 @@ impl<H: SearchHandler> Searcher<'_, H> {
             let best_move = best_move.unwrap();
 
             self.shared.cache_table.set(pos.board(), ply_index, CacheData {
-                kind: match best_eval {
-                    // No move was capable of raising alpha.
-                    // The actual value might be worse than this.
-                    _ if best_eval <= init_window.alpha => CacheDataKind::UpperBound,
-                    // The move was too good and this is a cut node.
-                    // The value might be even better if it were not cut off.
-                    _ if best_eval >= window.beta => CacheDataKind::LowerBound,
-                    // It's in the window. This is an exact value.
-                    _ => CacheDataKind::Exact
-                },
+                kind: init_window.bound(best_eval),
                 eval: best_eval,
-                depth,
+                depth: plies,
                 best_move
             });
 
-            if node == Node::Root {
-                self.search_result = Some(best_move);
-            }
-
             Ok(best_eval)
         })();
         self.data.game_history.pop();
@@ -398,17 +592,22 @@ impl<H: SearchHandler> Searcher<'_, H> {
         &mut self,
         pos: &Position,
         ply_index: u8,
+        qsearch_ply: u8,
         mut window: Window
     ) -> Eval {
         //TODO track history and repetitions in quiescence? This seems to lose Elo though...
         let result = (|| {
             self.stats.nodes += 1;
+            self.stats.seldepth = self.stats.seldepth.max(ply_index);
 
             match pos.board().status() {
                 GameStatus::Won => return Eval::mated_in(ply_index),
                 GameStatus::Drawn => return Eval::DRAW,
                 GameStatus::Ongoing => {}
             }
+            if ply_index >= MAX_PLY || qsearch_ply >= self.shared.search_params.qsearch_max_ply() {
+                return pos.evaluate();
+            }
             if let Some(eval) = oracle::oracle(pos.board()) {
                 return eval;
             }
@@ -424,18 +623,38 @@ impl<H: SearchHandler> Searcher<'_, H> {
                 }
             }
 
-            let mut best_eval = pos.evaluate();
-            window.narrow_alpha(best_eval);
-            if window.empty() {
-                return best_eval;
-            }
+            let in_check = !pos.board().checkers().is_empty();
+            let mut min_gain = None;
+            let mut best_eval = if in_check {
+                // Standing pat is only valid if we aren't forced to respond to a check.
+                // Every evasion could be worse than the static eval, so we search them all instead.
+                Eval::mated_in(ply_index)
+            } else {
+                let static_eval = pos.evaluate();
+                window.narrow_alpha(static_eval);
+                if window.empty() {
+                    return static_eval;
+                }
+                // CITE: Delta pruning.
+                // Captures that can't raise alpha even winning their victim and a margin are skipped.
+                // https://www.chessprogramming.org/Delta_Pruning
+                let margin = self.shared.search_params.qsearch_delta_margin();
+                if let (Some(margin), Some(static_eval), Some(alpha)) = (margin, static_eval.as_cp(), window.alpha.as_cp()) {
+                    let min = alpha as i32 - static_eval as i32 - margin as i32;
+                    min_gain = Some(min.clamp(SeeScore::MIN as i32, SeeScore::MAX as i32) as SeeScore);
+                }
+                static_eval
+            };
 
-            let mut move_list = QSearchMoveList::new(pos.board(), self);
+            // Quiet checks near the horizon can set up mates that captures alone would miss.
+            let include_checks = self.shared.search_params.qsearch_include_checks(qsearch_ply);
+            let mut move_list = QSearchMoveList::new(pos.board(), self, min_gain, include_checks);
             while let Some((_, (mv, _))) = move_list.pick() {
                 let child = pos.play_unchecked(mv);
                 let eval = -self.quiescence(
                     &child,
                     ply_index + 1,
+                    qsearch_ply + 1,
                     -window
                 );
 
@@ -454,13 +673,10 @@ impl<H: SearchHandler> Searcher<'_, H> {
         result
     }
 
-    fn repetitions(&self, board: &Board) -> usize {
-        self.data.game_history.iter()
-            .rev()
-            .take(board.halfmove_clock() as usize + 1)
-            .step_by(2) // Every second ply so it's our turn
-            .skip(1) // Skip our board
-            .filter(|&&hash| hash == board.hash())
-            .count()
+    /// Whether a position is scored as a draw by repetition. See `helpers::is_repetition_draw`.
+    fn is_repetition_draw(&self, board: &Board) -> bool {
+        // The last entry is our board, which has been pushed already.
+        let (_, history) = self.data.game_history.split_last().unwrap();
+        is_repetition_draw(history, self.shared.history.len(), board)
     }
 }
@@ -1,4 +1,5 @@
 use crate::eval::Eval;
+use super::cache::CacheDataKind;
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub struct Window {
@@ -12,44 +13,71 @@ impl Window {
         beta: Eval::MAX
     };
 
+    ///A window from `alpha` to `beta`, which must not be empty.
+    pub fn new(alpha: Eval, beta: Eval) -> Self {
+        debug_assert!(alpha < beta, "empty window: alpha {:?} >= beta {:?}", alpha, beta);
+        Self { alpha, beta }
+    }
+
     pub fn around(eval: Eval, bounds: Eval) -> Self {
-        Window {
-            alpha: eval.saturating_sub(bounds),
-            beta: eval.saturating_add(bounds)
-        }
+        Self::asymmetric(eval, bounds, bounds)
+    }
+
+    ///A window from `below` under `center` to `above` over it.
+    ///The bounds saturate at the largest centipawn scores, so mate scores always fall outside.
+    pub fn asymmetric(center: Eval, below: Eval, above: Eval) -> Self {
+        Self::new(center.saturating_sub(below), center.saturating_add(above))
     }
 
+    ///Raise alpha to `eval` if it's higher.
+    ///This may leave the window empty, which signals a beta cutoff.
     pub fn narrow_alpha(&mut self, eval: Eval) {
         self.alpha = self.alpha.max(eval);
     }
 
+    ///Lower beta to `eval` if it's lower.
+    ///This may leave the window empty, which signals an alpha cutoff.
     pub fn narrow_beta(&mut self, eval: Eval) {
         self.beta = self.beta.min(eval);
     }
 
+    ///Mate distance pruning: a mate found further from the root can never beat
+    ///a mate that's already been found, so bound the window by the fastest possible mates.
+    pub fn narrow_to_mate_distance(&mut self, ply_index: u8) {
+        self.narrow_alpha(Eval::mated_in(ply_index));
+        self.narrow_beta(Eval::mate_in(ply_index.saturating_add(1)));
+    }
+
     pub fn contains(&self, eval: Eval) -> bool {
         self.alpha < eval && eval < self.beta
     }
 
     ///Scout window to test for moves that can raise alpha
     pub fn null_window_alpha(&self) -> Self {
-        Self {
-            alpha: self.alpha,
-            beta: self.alpha + Eval::UNIT
-        }
+        Self::new(self.alpha, self.alpha + Eval::UNIT)
     }
 
     ///Scout window to test for beta cutoffs
     pub fn null_window_beta(&self) -> Self {
-        Self {
-            alpha: self.beta - Eval::UNIT,
-            beta: self.beta
-        }
+        Self::new(self.beta - Eval::UNIT, self.beta)
     }
 
     pub fn empty(self) -> bool {
         self.alpha >= self.beta
     }
+
+    ///What a fail-soft search with this window says about the true value, given the eval it returned.
+    ///An eval at or below alpha failed low and is an upper bound, and one at or above beta
+    ///failed high and is a lower bound. Only an eval inside the window is exact.
+    pub fn bound(&self, eval: Eval) -> CacheDataKind {
+        if eval <= self.alpha {
+            CacheDataKind::UpperBound
+        } else if eval >= self.beta {
+            CacheDataKind::LowerBound
+        } else {
+            CacheDataKind::Exact
+        }
+    }
 }
 
 impl std::ops::Neg for Window {
@@ -62,3 +90,114 @@ impl std::ops::Neg for Window {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::search::{SearchParams, SearchParamHandler};
+
+    #[test]
+    fn null_window_alpha() {
+        let window = Window::new(Eval::cp(-10), Eval::cp(20)).null_window_alpha();
+        assert_eq!(window, Window::new(Eval::cp(-10), Eval::cp(-9)));
+        assert!(!window.empty());
+        assert!(!window.contains(Eval::cp(-10)));
+        assert!(!window.contains(Eval::cp(-9)));
+    }
+
+    #[test]
+    fn narrow_to_mate_distance() {
+        let mut window = Window::INFINITY;
+        window.narrow_to_mate_distance(3);
+        assert_eq!(window, Window::new(Eval::mated_in(3), Eval::mate_in(4)));
+
+        // A window that's already narrower is left alone.
+        let mut window = Window::new(Eval::cp(-50), Eval::cp(50));
+        window.narrow_to_mate_distance(3);
+        assert_eq!(window, Window::new(Eval::cp(-50), Eval::cp(50)));
+
+        // A faster mate was already found, so nothing here can beat it.
+        let mut window = Window::new(Eval::mate_in(2), Eval::MAX);
+        window.narrow_to_mate_distance(4);
+        assert!(window.empty());
+    }
+
+    #[test]
+    fn contains() {
+        let window = Window::new(Eval::cp(-10), Eval::cp(10));
+        assert!(window.contains(Eval::ZERO));
+        assert!(window.contains(Eval::cp(9)));
+        assert!(!window.contains(Eval::cp(-10)));
+        assert!(!window.contains(Eval::cp(10)));
+        assert!(!window.contains(Eval::mate_in(1)));
+        assert!(!window.contains(Eval::mated_in(1)));
+    }
+
+    #[test]
+    fn asymmetric() {
+        let window = Window::asymmetric(Eval::cp(100), Eval::cp(10), Eval::cp(30));
+        assert_eq!(window, Window::new(Eval::cp(90), Eval::cp(130)));
+
+        // Saturates before reaching mate scores.
+        let window = Window::asymmetric(Eval::ZERO, Eval::MAX, Eval::MAX);
+        assert!(window.alpha.as_cp().is_some());
+        assert!(window.beta.as_cp().is_some());
+        assert!(window.contains(Eval::ZERO));
+        assert!(!window.contains(Eval::mate_in(u8::MAX)));
+        assert!(!window.contains(Eval::mated_in(u8::MAX)));
+    }
+
+    #[test]
+    fn empty() {
+        let mut window = Window::new(Eval::cp(-10), Eval::cp(10));
+        assert!(!window.empty());
+        window.narrow_alpha(Eval::cp(10));
+        assert!(window.empty());
+
+        let mut window = Window::new(Eval::cp(-10), Eval::cp(10));
+        window.narrow_beta(Eval::cp(-20));
+        assert!(window.empty());
+    }
+
+    #[test]
+    fn bound() {
+        let window = Window::new(Eval::cp(-10), Eval::cp(10));
+        assert_eq!(window.bound(Eval::cp(-50)), CacheDataKind::UpperBound);
+        assert_eq!(window.bound(Eval::cp(-10)), CacheDataKind::UpperBound);
+        assert_eq!(window.bound(Eval::ZERO), CacheDataKind::Exact);
+        assert_eq!(window.bound(Eval::cp(10)), CacheDataKind::LowerBound);
+        assert_eq!(window.bound(Eval::mate_in(1)), CacheDataKind::LowerBound);
+        assert_eq!(Window::INFINITY.bound(Eval::mated_in(1)), CacheDataKind::Exact);
+    }
+
+    #[test]
+    fn bound_after_narrowing() {
+        // Narrowing changes which evals are exact, so the bound has to be
+        // classified against the window the moves were actually searched with.
+        let window = Window::new(Eval::cp(-10), Eval::cp(10));
+        let mut narrowed = window;
+        narrowed.narrow_alpha(Eval::cp(5));
+        assert_eq!(window.bound(Eval::cp(5)), CacheDataKind::Exact);
+        assert_eq!(narrowed.bound(Eval::cp(5)), CacheDataKind::UpperBound);
+    }
+
+    #[test]
+    #[cfg(debug_assertions)]
+    #[should_panic]
+    fn asymmetric_zero_width() {
+        Window::asymmetric(Eval::ZERO, Eval::ZERO, Eval::ZERO);
+    }
+
+    #[test]
+    fn zero_aspiration_delta() {
+        let mut params = SearchParams::default();
+        params.aspiration.initial_delta = 0;
+        params.aspiration.widen_factor = 0;
+        let params = SearchParamHandler::new(params);
+        let delta = params.aspiration_initial_delta();
+        let window = Window::asymmetric(Eval::cp(40), delta, delta);
+        assert!(window.contains(Eval::cp(40)));
+        let widened = params.aspiration_widen(delta, 0).unwrap();
+        assert!(!Window::asymmetric(Eval::cp(40), widened, widened).empty());
+    }
+}
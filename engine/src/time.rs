@@ -63,9 +63,16 @@ pub enum StandardTimeManager {
 }
 
 impl StandardTimeManager {
-    pub fn standard(time_left: Duration, increment: Duration) -> Self {
+    /// Allocate time from the time left, the increment, and the moves left until the next time control.
+    /// A `moves_to_go` of `None` or zero is treated as sudden death.
+    pub fn standard(time_left: Duration, increment: Duration, moves_to_go: Option<u32>) -> Self {
+        let allocated = match moves_to_go {
+            // Leave a little spare so the last move before the time control isn't starved.
+            Some(moves_to_go) if moves_to_go > 0 => time_left / moves_to_go.saturating_add(1) + increment,
+            _ => (time_left + increment).mul_f32(0.025)
+        };
         Self::Standard {
-            allocated: (time_left + increment).mul_f32(0.025).min(time_left),
+            allocated: allocated.min(time_left),
             max_usage: time_left / 3,
             elapsed: Duration::ZERO
         }
@@ -94,3 +101,39 @@ impl TimeManager for StandardTimeManager {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn allocated(manager: StandardTimeManager) -> Duration {
+        match manager {
+            StandardTimeManager::Standard { allocated, .. } => allocated,
+            _ => unreachable!()
+        }
+    }
+
+    #[test]
+    fn zero_moves_to_go() {
+        let time_left = Duration::from_secs(60);
+        let increment = Duration::from_secs(1);
+        let zero = StandardTimeManager::standard(time_left, increment, Some(0));
+        let sudden_death = StandardTimeManager::standard(time_left, increment, None);
+        assert_eq!(allocated(zero), allocated(sudden_death));
+    }
+
+    #[test]
+    fn one_move_to_go() {
+        let time_left = Duration::from_secs(60);
+        let increment = Duration::from_secs(1);
+        let manager = StandardTimeManager::standard(time_left, increment, Some(1));
+        assert_eq!(allocated(manager), Duration::from_secs(31));
+    }
+
+    #[test]
+    fn max_moves_to_go() {
+        let time_left = Duration::from_secs(60);
+        let manager = StandardTimeManager::standard(time_left, Duration::ZERO, Some(u32::MAX));
+        assert!(allocated(manager) < time_left);
+    }
+}
@@ -0,0 +1,58 @@
+//! Checks that breaking root ties makes the chosen move reproducible.
+
+use std::num::NonZeroU8;
+
+use cozy_chess::*;
+use tantabus::search::*;
+
+const POSITIONS: &[&str] = &[
+    "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1",
+    // Only kings and pawns, so many root moves are likely to evaluate the same.
+    "4k3/pppppppp/8/8/8/8/PPPPPPPP/4K3 w - - 0 1"
+];
+
+#[derive(Default)]
+struct Handler {
+    result: Option<SearchResult>
+}
+
+impl SearchHandler for Handler {
+    fn stop_search(&self, _nodes: u64) -> bool {
+        false
+    }
+
+    fn new_result(&mut self, result: SearchResult) {
+        self.result = Some(result);
+    }
+}
+
+fn search(board: &Board) -> SearchResult {
+    let mut handler = Handler::default();
+    let options = EngineOptions {
+        max_depth: NonZeroU8::new(6).unwrap(),
+        break_root_ties: true,
+        ..EngineOptions::default()
+    };
+    let mut engine = Engine::new(
+        &mut handler,
+        board.clone(),
+        Vec::new(),
+        options,
+        SearchParams::default(),
+        CacheTable::new_with_size(1_000_000).unwrap()
+    );
+    engine.search();
+    drop(engine);
+    handler.result.unwrap()
+}
+
+#[test]
+fn same_move_every_run() {
+    for &fen in POSITIONS {
+        let board = Board::from_fen(fen, false).unwrap();
+        let first = search(&board);
+        let second = search(&board);
+        assert_eq!(first.mv, second.mv, "{}", fen);
+        assert_eq!(first.eval, second.eval, "{}", fen);
+    }
+}
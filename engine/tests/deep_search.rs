@@ -0,0 +1,60 @@
+//! Searches positions that go very deep as far as a node budget allows,
+//! checking that nothing overflows or indexes out of bounds near [`MAX_PLY`].
+
+use std::num::NonZeroU8;
+
+use cozy_chess::*;
+use tantabus::search::*;
+
+const NODE_BUDGET: u64 = 2_000_000;
+
+const POSITIONS: &[&str] = &[
+    // Both queens can give long sequences of checks, and every check is extended.
+    "6k1/6p1/6Q1/8/8/8/q5PP/7K w - - 0 1",
+    // Little can happen, so iterations are cheap and go deep quickly.
+    "8/8/3k4/1p1p1p1p/1P1P1P1P/3K4/8/8 w - - 0 1"
+];
+
+#[derive(Default)]
+struct Handler {
+    result: Option<SearchResult>,
+    nodes: u64
+}
+
+impl SearchHandler for Handler {
+    fn stop_search(&self, nodes: u64) -> bool {
+        self.nodes + nodes >= NODE_BUDGET
+    }
+
+    fn new_result(&mut self, result: SearchResult) {
+        self.nodes += result.nodes;
+        self.result = Some(result);
+    }
+}
+
+#[test]
+fn search_to_max_depth() {
+    for &fen in POSITIONS {
+        let board = Board::from_fen(fen, false).unwrap();
+        let mut handler = Handler::default();
+        let options = EngineOptions {
+            max_depth: NonZeroU8::new(u8::MAX).unwrap(),
+            ..EngineOptions::default()
+        };
+        let mut engine = Engine::new(
+            &mut handler,
+            board.clone(),
+            Vec::new(),
+            options,
+            SearchParams::default(),
+            CacheTable::new_with_size(1_000_000).unwrap()
+        );
+        engine.search();
+        drop(engine);
+
+        let result = handler.result.unwrap();
+        let mv = result.mv.unwrap();
+        assert!(board.is_legal(mv), "{}: {} isn't legal", fen, mv);
+        assert!(result.seldepth <= MAX_PLY, "{}: seldepth {} is past the maximum", fen, result.seldepth);
+    }
+}
@@ -0,0 +1,68 @@
+//! Checks moves that quiescence search has to find beyond the main search's horizon.
+//!
+//! Each position is searched to depth 1 with the side to move out of check,
+//! so the replies to the root moves are only searched by quiescence search.
+//! The replies mate, so the scores don't depend on the evaluation.
+
+use std::num::NonZeroU8;
+
+use cozy_chess::*;
+use tantabus::eval::Eval;
+use tantabus::search::*;
+
+#[derive(Default)]
+struct Handler {
+    result: Option<SearchResult>
+}
+
+impl SearchHandler for Handler {
+    fn stop_search(&self, _nodes: u64) -> bool {
+        false
+    }
+
+    fn new_result(&mut self, result: SearchResult) {
+        self.result = Some(result);
+    }
+}
+
+fn depth_1_search(fen: &str, params: SearchParams) -> SearchResult {
+    let board = Board::from_fen(fen, false).unwrap();
+    let mut handler = Handler::default();
+    let options = EngineOptions {
+        max_depth: NonZeroU8::new(1).unwrap(),
+        ..EngineOptions::default()
+    };
+    let mut engine = Engine::new(
+        &mut handler,
+        board,
+        Vec::new(),
+        options,
+        params,
+        CacheTable::new_with_size(1_000_000).unwrap()
+    );
+    engine.search();
+    drop(engine);
+    handler.result.unwrap()
+}
+
+#[test]
+fn en_passant_capture() {
+    // White can only play e2e3 or e2e4, and either way dxe3 is mate.
+    // After e2e4, that's only found if quiescence search tries the en passant capture.
+    let fen = "k5r1/8/8/2b4b/3p4/8/4PK2/r7 w - - 0 1";
+    let mut params = SearchParams::default();
+    params.qsearch.delta_pruning = true;
+    let result = depth_1_search(fen, params);
+    assert_eq!(result.eval, Eval::mated_in(2));
+}
+
+#[test]
+fn quiet_check_mate() {
+    // White can only play a2a3 or a2a4, and either way Re1 is mate.
+    // Re1 isn't a capture, so that's only found if quiescence search tries quiet checks.
+    let fen = "4r1r1/2k5/8/8/8/7p/P6P/7K w - - 0 1";
+    let mut params = SearchParams::default();
+    params.qsearch.check_plies = 1;
+    let result = depth_1_search(fen, params);
+    assert_eq!(result.eval, Eval::mated_in(2));
+}
@@ -0,0 +1,134 @@
+//! Checks the search against a plain alpha-beta search with no pruning, reductions or cache.
+//!
+//! Every position has a forced mate within the search depth, so the true score
+//! is a mate score that doesn't depend on the evaluation at the leaves.
+//! Any pruning that loses or invents the mate shows up as a different score or move.
+
+use std::convert::TryInto;
+
+use cozy_chess::*;
+use tantabus::eval::Eval;
+use tantabus::search::*;
+
+const POSITIONS: &[(&str, u8)] = &[
+    // Back rank mate in 1.
+    ("6k1/5ppp/8/8/8/8/5PPP/3R2K1 w - - 0 1", 4),
+    // The same with black to move.
+    ("3r2k1/5ppp/8/8/8/8/5PPP/6K1 b - - 0 1", 4),
+    // Queen and king mate in 1.
+    ("7k/8/5K2/8/8/8/8/6Q1 w - - 0 1", 4),
+    // Rook and king mate in 2, where the first move is quiet.
+    ("k7/8/2K5/8/8/8/8/7R w - - 0 1", 5)
+];
+
+#[derive(Default)]
+struct Handler {
+    result: Option<SearchResult>
+}
+
+impl SearchHandler for Handler {
+    fn stop_search(&self, _nodes: u64) -> bool {
+        false
+    }
+
+    fn new_result(&mut self, result: SearchResult) {
+        self.result = Some(result);
+    }
+}
+
+fn legal_moves(board: &Board) -> Vec<Move> {
+    let mut moves = Vec::new();
+    board.generate_moves(|piece_moves| {
+        moves.extend(piece_moves);
+        false
+    });
+    moves
+}
+
+/// Plain fail-hard alpha-beta. Leaves score zero, since only mate scores are compared.
+fn reference_search(board: &Board, depth: u8, ply_index: u8, mut alpha: Eval, beta: Eval) -> Eval {
+    match board.status() {
+        GameStatus::Won => return Eval::mated_in(ply_index),
+        GameStatus::Drawn => return Eval::DRAW,
+        GameStatus::Ongoing => {}
+    }
+    if depth == 0 {
+        return Eval::ZERO;
+    }
+    for mv in legal_moves(board) {
+        let mut child = board.clone();
+        child.play_unchecked(mv);
+        let eval = -reference_search(&child, depth - 1, ply_index + 1, -beta, -alpha);
+        if eval >= beta {
+            return beta;
+        }
+        if eval > alpha {
+            alpha = eval;
+        }
+    }
+    alpha
+}
+
+/// The exact reference score and every root move that achieves it.
+fn reference_best_moves(board: &Board, depth: u8) -> (Eval, Vec<Move>) {
+    let mut best_eval = Eval::MIN;
+    let mut best_moves = Vec::new();
+    for mv in legal_moves(board) {
+        let mut child = board.clone();
+        child.play_unchecked(mv);
+        let eval = -reference_search(&child, depth - 1, 1, Eval::MIN, Eval::MAX);
+        if eval > best_eval {
+            best_eval = eval;
+            best_moves.clear();
+        }
+        if eval == best_eval {
+            best_moves.push(mv);
+        }
+    }
+    (best_eval, best_moves)
+}
+
+fn engine_search(board: &Board, depth: u8) -> SearchResult {
+    let mut handler = Handler::default();
+    let options = EngineOptions {
+        max_depth: depth.try_into().unwrap(),
+        ..EngineOptions::default()
+    };
+    let mut engine = Engine::new(
+        &mut handler,
+        board.clone(),
+        Vec::new(),
+        options,
+        SearchParams::default(),
+        CacheTable::new_with_size(1_000_000).unwrap()
+    );
+    engine.search();
+    drop(engine);
+    handler.result.unwrap()
+}
+
+#[test]
+fn search_matches_reference() {
+    for &(fen, depth) in POSITIONS {
+        let board = Board::from_fen(fen, false).unwrap();
+        let (reference_eval, reference_moves) = reference_best_moves(&board, depth);
+        assert!(
+            reference_eval.as_cp().is_none(),
+            "{}: the reference search found no mate, so the position doesn't test anything",
+            fen
+        );
+
+        let result = engine_search(&board, depth);
+        assert_eq!(result.depth, depth, "{}: the search stopped early", fen);
+        assert_eq!(result.bound, CacheDataKind::Exact, "{}: a completed iteration isn't exact", fen);
+        assert_eq!(result.eval, reference_eval, "{}: score differs from the reference", fen);
+        let mv = result.mv.unwrap();
+        assert!(
+            reference_moves.contains(&mv),
+            "{}: {} isn't one of the reference's best moves {:?}",
+            fen,
+            mv,
+            reference_moves
+        );
+    }
+}
@@ -0,0 +1,65 @@
+//! Checks that a search with no time at all still finds a legal move.
+
+use std::num::NonZeroU8;
+use std::time::{Duration, Instant};
+
+use cozy_chess::*;
+use tantabus::search::*;
+use tantabus::time::*;
+
+const POSITIONS: &[&str] = &[
+    "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1",
+    "r1bqkb1r/pppp1ppp/2n2n2/4p2Q/2B1P3/8/PPPP1PPP/RNB1K1NR w KQkq - 4 4"
+];
+
+struct Handler {
+    time_manager: StandardTimeManager,
+    last_update: Instant,
+    time_left: Duration,
+    result: Option<SearchResult>
+}
+
+impl SearchHandler for Handler {
+    fn stop_search(&self, _nodes: u64) -> bool {
+        self.time_left <= self.last_update.elapsed()
+    }
+
+    fn new_result(&mut self, result: SearchResult) {
+        self.time_left = self.time_manager.update(&result, self.last_update.elapsed());
+        self.last_update = Instant::now();
+        self.result = Some(result);
+    }
+}
+
+#[test]
+fn zero_move_time() {
+    for &fen in POSITIONS {
+        let board = Board::from_fen(fen, false).unwrap();
+        let mut handler = Handler {
+            time_manager: StandardTimeManager::Fixed(Duration::ZERO),
+            last_update: Instant::now(),
+            // Out of time before the search even starts.
+            time_left: Duration::ZERO,
+            result: None
+        };
+        let options = EngineOptions {
+            max_depth: NonZeroU8::new(u8::MAX).unwrap(),
+            ..EngineOptions::default()
+        };
+        let mut engine = Engine::new(
+            &mut handler,
+            board.clone(),
+            Vec::new(),
+            options,
+            SearchParams::default(),
+            CacheTable::new_with_size(1_000_000).unwrap()
+        );
+        engine.search();
+        drop(engine);
+
+        let result = handler.result.expect("no result");
+        let mv = result.mv.unwrap();
+        assert!(board.is_legal(mv), "{}: {} isn't legal", fen, mv);
+        assert_eq!(result.depth, 1, "{}", fen);
+    }
+}
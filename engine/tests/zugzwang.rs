@@ -0,0 +1,77 @@
+//! Checks that null move pruning doesn't hide mates that come from zugzwang.
+//!
+//! In the position, white mates in 2 by waiting: the black bishop blocks the e-file,
+//! and every move it has unblocks it for Re8#. If black could pass, it wouldn't have
+//! to unblock the file, so a null move search sees a much better position for black than
+//! there really is. Each test uses a different guard against that.
+
+use std::num::NonZeroU8;
+
+use cozy_chess::*;
+use tantabus::eval::Eval;
+use tantabus::search::*;
+
+const FEN: &str = "7k/2p2K1p/2p4P/2p5/2P2P2/4b3/3PRP2/8 w - - 0 1";
+/// Every waiting move that keeps the rook on the e-file.
+const MATING_MOVES: &[&str] = &["d2d3", "e2e1", "f2f3"];
+
+#[derive(Default)]
+struct Handler {
+    result: Option<SearchResult>
+}
+
+impl SearchHandler for Handler {
+    fn stop_search(&self, _nodes: u64) -> bool {
+        false
+    }
+
+    fn new_result(&mut self, result: SearchResult) {
+        self.result = Some(result);
+    }
+}
+
+fn assert_finds_mate(depth: u8, params: SearchParams) {
+    let board = Board::from_fen(FEN, false).unwrap();
+    let mut handler = Handler::default();
+    let options = EngineOptions {
+        max_depth: NonZeroU8::new(depth).unwrap(),
+        ..EngineOptions::default()
+    };
+    let mut engine = Engine::new(
+        &mut handler,
+        board,
+        Vec::new(),
+        options,
+        params,
+        CacheTable::new_with_size(1_000_000).unwrap()
+    );
+    engine.search();
+    drop(engine);
+    let result = handler.result.unwrap();
+
+    assert_eq!(result.eval, Eval::mate_in(3));
+    let mv = result.mv.unwrap().to_string();
+    assert!(MATING_MOVES.contains(&mv.as_str()), "{} doesn't mate", mv);
+}
+
+#[test]
+fn verified_null_move_pruning() {
+    let mut params = SearchParams::default();
+    params.nmp.verify_min_depth = 1;
+    assert_finds_mate(12, params);
+}
+
+#[test]
+fn default_null_move_verification() {
+    // Deep enough that the null move searches near the root are verified.
+    assert_finds_mate(16, SearchParams::default());
+}
+
+#[test]
+fn null_move_material_guard() {
+    // Without verification, skip null moves for black, whose only piece is a bishop.
+    let mut params = SearchParams::default();
+    params.nmp.verify_min_depth = u8::MAX;
+    params.nmp.min_non_pawn_material = 4;
+    assert_finds_mate(12, params);
+}
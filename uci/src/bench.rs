@@ -1,3 +1,4 @@
+use std::num::{NonZeroU8, NonZeroU32};
 use std::time::{Duration, Instant};
 
 use tantabus::search::*;
@@ -32,23 +33,24 @@ const POSITIONS: &[&str] = &[
     "8/1p2k3/4rp2/p2R3Q/2q2B2/6P1/5P1P/6K1 b - - 14 73",
 ];
 
+// The bench signature must be stable across runs and machines,
+// so everything that can influence the node count is fixed here.
 const DEPTH: u8 = 10;
+const THREADS: u32 = 1;
 const CACHE: usize = 16_000_000;
 
 #[derive(Default)]
 struct BenchHandler {
-    nodes: u64,
-    prev_result: Option<SearchResult>
+    nodes: u64
 }
 
 impl SearchHandler for BenchHandler {
     fn stop_search(&self, _nodes: u64) -> bool {
-        self.prev_result.as_ref().map(|r| r.depth >= DEPTH).unwrap_or_default()
+        false
     }
 
     fn new_result(&mut self, search_result: SearchResult) {
         self.nodes += search_result.nodes;
-        self.prev_result = Some(search_result);
     }
 }
 
@@ -58,11 +60,16 @@ pub fn bench() {
     for position in POSITIONS {
         let init_pos = position.parse().unwrap();
         let mut handler = BenchHandler::default();
+        let options = EngineOptions {
+            max_depth: NonZeroU8::new(DEPTH).unwrap(),
+            threads: NonZeroU32::new(THREADS).unwrap(),
+            ..EngineOptions::default()
+        };
         let mut state = Engine::new(
             &mut handler,
             init_pos,
             Vec::new(),
-            EngineOptions::default(),
+            options,
             SearchParams::default(),
             CacheTable::new_with_size(CACHE).unwrap()
         );
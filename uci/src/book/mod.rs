@@ -0,0 +1,183 @@
+use std::io::{Error, ErrorKind};
+use std::path::Path;
+
+use cozy_chess::*;
+
+mod random64;
+
+use random64::RANDOM64;
+
+const ENTRY_SIZE: usize = 16;
+
+// CITE: The Polyglot opening book format.
+// http://hgm.nubati.net/book_format.html
+/// The Polyglot hash of a position, which is how book entries are looked up.
+pub fn polyglot_key(board: &Board) -> u64 {
+    let mut key = 0;
+    for &color in &Color::ALL {
+        for &piece in &Piece::ALL {
+            // Black pieces come before white pieces of the same kind.
+            let kind = piece as usize * 2 + (color == Color::White) as usize;
+            for square in board.colored_pieces(color, piece) {
+                key ^= RANDOM64[64 * kind + square as usize];
+            }
+        }
+    }
+
+    let white_rights = board.castle_rights(Color::White);
+    let black_rights = board.castle_rights(Color::Black);
+    let castle_rights = [
+        white_rights.short,
+        white_rights.long,
+        black_rights.short,
+        black_rights.long
+    ];
+    for (i, right) in castle_rights.iter().enumerate() {
+        if right.is_some() {
+            key ^= RANDOM64[768 + i];
+        }
+    }
+
+    // The en passant file only counts if a pawn could actually capture there.
+    let side_to_move = board.side_to_move();
+    if let Some(file) = board.en_passant() {
+        let rank = match side_to_move {
+            Color::White => Rank::Sixth,
+            Color::Black => Rank::Third
+        };
+        let target = Square::new(file, rank);
+        let capturers = get_pawn_attacks(target, !side_to_move)
+            & board.colored_pieces(side_to_move, Piece::Pawn);
+        if !capturers.is_empty() {
+            key ^= RANDOM64[772 + file as usize];
+        }
+    }
+
+    if side_to_move == Color::White {
+        key ^= RANDOM64[780];
+    }
+    key
+}
+
+#[derive(Debug, Clone, Copy)]
+struct BookEntry {
+    key: u64,
+    mv: u16,
+    weight: u16
+}
+
+/// A Polyglot opening book.
+pub struct Book {
+    /// Sorted by key.
+    entries: Vec<BookEntry>
+}
+
+impl Book {
+    /// Read a Polyglot `.bin` book. Entries are 16 bytes, big-endian:
+    /// a `u64` key, a `u16` move, a `u16` weight and a `u32` learn value, which is ignored.
+    pub fn open(path: impl AsRef<Path>) -> std::io::Result<Self> {
+        let bytes = std::fs::read(path)?;
+        if bytes.len() % ENTRY_SIZE != 0 {
+            return Err(Error::new(ErrorKind::InvalidData, "Book size isn't a whole number of entries."));
+        }
+        let mut entries = bytes.chunks_exact(ENTRY_SIZE)
+            .map(|entry| BookEntry {
+                key: u64::from_be_bytes(entry[0..8].try_into().unwrap()),
+                mv: u16::from_be_bytes(entry[8..10].try_into().unwrap()),
+                weight: u16::from_be_bytes(entry[10..12].try_into().unwrap())
+            })
+            .collect::<Vec<_>>();
+        // Books are meant to be sorted already, but don't rely on it.
+        entries.sort_by_key(|entry| entry.key);
+        Ok(Self { entries })
+    }
+
+    /// The legal book moves for a position and their weights.
+    pub fn moves(&self, board: &Board) -> Vec<(Move, u16)> {
+        let key = polyglot_key(board);
+        let start = self.entries.partition_point(|entry| entry.key < key);
+        self.entries[start..]
+            .iter()
+            .take_while(|entry| entry.key == key)
+            .filter_map(|entry| {
+                let mv = decode_move(entry.mv)?;
+                board.is_legal(mv).then(|| (mv, entry.weight))
+            })
+            .collect()
+    }
+
+    /// Pick a book move at random, weighted by the book's weights.
+    /// `seed` is any number, such as the time. Returns `None` if the position is out of book.
+    pub fn pick(&self, board: &Board, seed: u64) -> Option<Move> {
+        let moves = self.moves(board);
+        let total = moves.iter().map(|&(_, weight)| weight as u64).sum::<u64>();
+        if total == 0 {
+            return None;
+        }
+        let mut target = seed % total;
+        for (mv, weight) in moves {
+            if target < weight as u64 {
+                return Some(mv);
+            }
+            target -= weight as u64;
+        }
+        None
+    }
+}
+
+/// Decode a Polyglot move. Castling is encoded as the king capturing its own rook,
+/// the same way cozy-chess encodes it.
+fn decode_move(mv: u16) -> Option<Move> {
+    let to = Square::index((mv & 0b111111) as usize);
+    let from = Square::index((mv >> 6 & 0b111111) as usize);
+    let promotion = match mv >> 12 & 0b111 {
+        0 => None,
+        1 => Some(Piece::Knight),
+        2 => Some(Piece::Bishop),
+        3 => Some(Piece::Rook),
+        4 => Some(Piece::Queen),
+        _ => return None
+    };
+    Some(Move {
+        from,
+        to,
+        promotion
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // The test positions given with the Polyglot book format.
+    const KEYS: &[(&str, u64)] = &[
+        ("rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1", 0x463B96181691FC9C),
+        ("rnbqkbnr/pppppppp/8/8/4P3/8/PPPP1PPP/RNBQKBNR b KQkq e3 0 1", 0x823C9B50FD114196),
+        ("rnbqkbnr/ppp1pppp/8/3p4/4P3/8/PPPP1PPP/RNBQKBNR w KQkq d6 0 2", 0x0756B94461C50FB0),
+        ("rnbqkbnr/ppp1pppp/8/3pP3/8/8/PPPP1PPP/RNBQKBNR b KQkq - 0 2", 0x662FAFB965DB29D4),
+        ("rnbqkbnr/ppp1p1pp/8/3pPp2/8/8/PPPP1PPP/RNBQKBNR w KQkq f6 0 3", 0x22A48B5A8E47FF78),
+        ("rnbqkbnr/ppp1p1pp/8/3pPp2/8/8/PPPPKPPP/RNBQ1BNR b kq - 0 3", 0x652A607CA3F242C1),
+        ("rnbq1bnr/ppp1pkpp/8/3pPp2/8/8/PPPPKPPP/RNBQ1BNR w - - 0 4", 0x00FDD303C946BDD9),
+        ("rnbqkbnr/p1pppppp/8/8/PpP4P/8/1P1PPPP1/RNBQKBNR b KQkq c3 0 3", 0x3C8123EA7B067637),
+        ("rnbqkbnr/p1pppppp/8/8/P6P/R1p5/1P1PPPP1/1NBQKBNR b Kkq - 0 4", 0x5C3F9B829B279560)
+    ];
+
+    #[test]
+    fn polyglot_keys() {
+        for &(fen, key) in KEYS {
+            let board = Board::from_fen(fen, false).unwrap();
+            assert_eq!(polyglot_key(&board), key, "{}", fen);
+        }
+    }
+
+    #[test]
+    fn decode_castling() {
+        // e1h1
+        let mv = decode_move(0b000_000_100_000_111).unwrap();
+        assert_eq!(mv, Move {
+            from: Square::E1,
+            to: Square::H1,
+            promotion: None
+        });
+    }
+}
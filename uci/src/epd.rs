@@ -0,0 +1,163 @@
+use std::fs;
+use std::io::{BufRead, BufReader};
+use std::num::NonZeroU32;
+use std::time::{Duration, Instant};
+
+use cozy_chess::*;
+use tantabus::san::san_to_move;
+use tantabus::search::*;
+use vampirc_uci::UciMove;
+
+use crate::convert::UciMoveInto;
+
+const CACHE: usize = 16_000_000;
+
+struct EpdHandler {
+    start: Instant,
+    time: Duration,
+    best_move: Option<Move>
+}
+
+impl SearchHandler for EpdHandler {
+    fn stop_search(&self, _nodes: u64) -> bool {
+        self.start.elapsed() >= self.time
+    }
+
+    fn new_result(&mut self, result: SearchResult) {
+        self.best_move = result.mv;
+    }
+}
+
+struct EpdEntry {
+    board: Board,
+    id: Option<String>,
+    best_moves: Vec<String>,
+    avoid_moves: Vec<String>
+}
+
+impl EpdEntry {
+    /// Parse an EPD line: four FEN fields followed by `;`-terminated operations.
+    fn parse(line: &str) -> Result<Self, String> {
+        let mut fields = line.splitn(5, ' ');
+        let mut fen = String::new();
+        for _ in 0..4 {
+            let field = fields.next().ok_or("Expected 4 FEN fields.")?;
+            fen.push_str(field);
+            fen.push(' ');
+        }
+        fen.push_str("0 1");
+        let board = Board::from_fen(&fen, false)
+            .map_err(|e| format!("Invalid position {}: {:?}", fen, e))?;
+
+        let mut entry = Self {
+            board,
+            id: None,
+            best_moves: Vec::new(),
+            avoid_moves: Vec::new()
+        };
+        for operation in fields.next().unwrap_or("").split(';') {
+            let mut operands = operation.split_whitespace();
+            let opcode = match operands.next() {
+                Some(opcode) => opcode,
+                None => continue
+            };
+            let operands = operands.map(|operand| operand.trim_matches('"').to_owned());
+            match opcode {
+                "id" => entry.id = Some(operands.collect::<Vec<_>>().join(" ")),
+                "bm" => entry.best_moves.extend(operands),
+                "am" => entry.avoid_moves.extend(operands),
+                _ => {}
+            }
+        }
+        Ok(entry)
+    }
+}
+
+/// Whether `mv` is one of the moves in `set`, given in either SAN or UCI notation.
+fn move_in_set(board: &Board, mv: Move, set: &[String]) -> bool {
+    let uci_mv: UciMove = mv.uci_move_into(board, false);
+    set.iter().any(|other| {
+        san_to_move(board, other) == Some(mv) || uci_mv.to_string() == *other
+    })
+}
+
+pub fn epd(mut args: impl Iterator<Item=String>) {
+    let mut path = None;
+    let mut time = Duration::from_millis(1000);
+    let mut threads = NonZeroU32::new(1).unwrap();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--time" => {
+                let millis = args.next().and_then(|t| t.parse().ok())
+                    .expect("--time expects a time in milliseconds");
+                time = Duration::from_millis(millis);
+            }
+            "--threads" => {
+                threads = args.next().and_then(|t| t.parse().ok())
+                    .expect("--threads expects a positive number");
+            }
+            _ => path = Some(arg)
+        }
+    }
+    let path = path.expect("Usage: epd <suite.epd> [--time <ms>] [--threads <n>]");
+    let file = BufReader::new(fs::File::open(&path).expect("Failed to open EPD file"));
+
+    let mut total = 0;
+    let mut solved = 0;
+    let mut total_time = Duration::ZERO;
+    for (line_index, line) in file.lines().enumerate() {
+        let line = line.expect("Failed to read EPD file");
+        let line = line.trim();
+        if line.is_empty() {
+            continue;
+        }
+        let entry = match EpdEntry::parse(line) {
+            Ok(entry) => entry,
+            Err(error) => {
+                eprintln!("Skipping line {}: {}", line_index + 1, error);
+                continue;
+            }
+        };
+        if entry.board.status() != GameStatus::Ongoing {
+            eprintln!("Skipping line {}: No legal moves.", line_index + 1);
+            continue;
+        }
+
+        let mut handler = EpdHandler {
+            start: Instant::now(),
+            time,
+            best_move: None
+        };
+        let options = EngineOptions {
+            threads,
+            ..EngineOptions::default()
+        };
+        let mut engine = Engine::new(
+            &mut handler,
+            entry.board.clone(),
+            Vec::new(),
+            options,
+            SearchParams::default(),
+            CacheTable::new_with_size(CACHE).unwrap()
+        );
+        engine.search();
+        total_time += handler.start.elapsed();
+
+        let mv = handler.best_move.unwrap();
+        let uci_mv: UciMove = mv.uci_move_into(&entry.board, false);
+        let found_best = entry.best_moves.is_empty() || move_in_set(&entry.board, mv, &entry.best_moves);
+        let avoided = !move_in_set(&entry.board, mv, &entry.avoid_moves);
+        let success = found_best && avoided;
+        total += 1;
+        if success {
+            solved += 1;
+        }
+        println!(
+            "{} {} {}",
+            entry.id.unwrap_or_else(|| format!("line {}", line_index + 1)),
+            uci_mv,
+            if success { "ok" } else { "fail" }
+        );
+    }
+    println!("{}/{} solved in {} ms", solved, total, total_time.as_millis());
+}
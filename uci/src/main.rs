@@ -1,8 +1,10 @@
 use std::convert::TryInto;
+use std::num::NonZeroU32;
 use std::io::{BufRead, BufReader, Write, stdin};
-use std::time::{Instant, Duration};
+use std::time::{Instant, Duration, SystemTime, UNIX_EPOCH};
 use std::sync::Arc;
 use std::sync::mpsc::{channel, Sender};
+use std::thread::JoinHandle;
 use std::sync::atomic::{AtomicBool, Ordering};
 
 use cozy_chess::*;
@@ -14,8 +16,12 @@ use tantabus::time::*;
 mod options;
 mod convert;
 mod bench;
+mod epd;
+mod strength;
+mod book;
 
 use options::UciOptionsHandler;
+use strength::StrengthLimit;
 use convert::*;
 
 const ENGINE_NAME: &str = concat!("Tantabus ", env!("CARGO_PKG_VERSION"));
@@ -29,11 +35,18 @@ struct UciHandler {
     search_terminator: Arc<AtomicBool>,
     event_sink: Sender<Event>,
     total_nodes: u64,
-    prev_result: Option<SearchResult>
+    prev_result: Option<SearchResult>,
+    search_id: u64,
+    max_nodes: Option<u64>
 }
 
 impl SearchHandler for UciHandler {
     fn stop_search(&self, nodes: u64) -> bool {
+        if let Some(max_nodes) = self.max_nodes {
+            if self.total_nodes + nodes >= max_nodes {
+                return true;
+            }
+        }
         if nodes % 1024 != 0 {
             return false;
         }
@@ -44,11 +57,26 @@ impl SearchHandler for UciHandler {
     fn new_result(&mut self, mut result: SearchResult) {
         self.time_left = self.time_manager.update(&result, self.last_update.elapsed());
         self.last_update = Instant::now();
-        self.prev_result = Some(result.clone());
         self.total_nodes += result.nodes;
         result.nodes = self.total_nodes;
+        self.prev_result = Some(result.clone());
+        self.event_sink.send(
+            Event::EngineSearchUpdate(
+                self.search_id,
+                EngineSearchResult::SearchInfo(
+                    result,
+                    self.search_begin.elapsed()
+                )
+            )
+        ).unwrap();
+    }
+
+    fn window_failed(&mut self, mut result: SearchResult) {
+        // Only reported, so the time manager still goes by completed iterations.
+        result.nodes += self.total_nodes;
         self.event_sink.send(
             Event::EngineSearchUpdate(
+                self.search_id,
                 EngineSearchResult::SearchInfo(
                     result,
                     self.search_begin.elapsed()
@@ -59,12 +87,13 @@ impl SearchHandler for UciHandler {
 }
 
 impl UciHandler {
-    fn finish(mut self, cache_table: CacheTable) {
+    fn finish(mut self) {
         self.event_sink.send(
             Event::EngineSearchUpdate(
+                self.search_id,
                 EngineSearchResult::SearchFinished(
                     self.prev_result.take().unwrap(),
-                    cache_table
+                    self.search_begin.elapsed()
                 )
             )
         ).unwrap();
@@ -73,7 +102,58 @@ impl UciHandler {
 
 enum EngineSearchResult {
     SearchInfo(SearchResult, Duration),
-    SearchFinished(SearchResult, CacheTable)
+    SearchFinished(SearchResult, Duration)
+}
+
+fn eval_to_uci_score(eval: Eval, bound: CacheDataKind) -> UciInfoAttribute {
+    let mut score = match eval.kind() {
+        EvalKind::Centipawn(cp) => UciInfoAttribute::from_centipawns(cp as i32),
+        EvalKind::MateIn(m) => UciInfoAttribute::from_mate(((m + 1) / 2) as i8),
+        EvalKind::MatedIn(m) => UciInfoAttribute::from_mate(-(((m + 1) / 2) as i8))
+    };
+    if let UciInfoAttribute::Score { lower_bound, upper_bound, .. } = &mut score {
+        match bound {
+            CacheDataKind::Exact => {}
+            CacheDataKind::LowerBound => *lower_bound = Some(true),
+            CacheDataKind::UpperBound => *upper_bound = Some(true)
+        }
+    }
+    score
+}
+
+/// The board after playing out the moves of the current `position` command.
+fn current_board(position: &Option<(Board, Vec<Move>)>) -> Board {
+    let mut board = Board::default();
+    if let Some((init_pos, moves)) = position {
+        board = init_pos.clone();
+        for &mv in moves {
+            board.play_unchecked(mv);
+        }
+    }
+    board
+}
+
+/// The reply we expect to our best move, taken from the principal variation.
+fn ponder_move(board: &Board, result: &SearchResult, chess960: bool) -> Option<UciMove> {
+    let mv = result.mv?;
+    if result.principal_variation.first() != Some(&mv) {
+        return None;
+    }
+    let &ponder_mv = result.principal_variation.get(1)?;
+    let mut board = board.clone();
+    board.play_unchecked(mv);
+    // The PV is read back from the cache table, so don't trust it blindly.
+    if board.status() != GameStatus::Ongoing || !board.is_legal(ponder_mv) {
+        return None;
+    }
+    Some(ponder_mv.uci_move_into(&board, chess960))
+}
+
+/// A seed taken from the clock so repeated games don't play out the same way.
+fn clock_seed() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |time| time.as_nanos() as u64)
 }
 
 fn send_message(message: UciMessage) {
@@ -81,12 +161,55 @@ fn send_message(message: UciMessage) {
     std::io::stdout().flush().unwrap();
 }
 
+fn send_error(error: String) {
+    send_message(UciMessage::info_string(format!("[ERROR] {}", error)));
+}
+
+/// Some GUIs and scripts omit the move counters, as in EPD.
+/// Fill in the halfmove clock and fullmove number if they're missing.
+fn normalize_fen(fen: &str) -> Option<String> {
+    const DEFAULT_COUNTERS: [&str; 2] = ["0", "1"];
+    let mut fields = fen.split_whitespace().collect::<Vec<_>>();
+    if !(4..=6).contains(&fields.len()) {
+        return None;
+    }
+    let missing = 6 - fields.len();
+    fields.extend_from_slice(&DEFAULT_COUNTERS[2 - missing..]);
+    Some(fields.join(" "))
+}
+
+fn parse_position(fen: Option<UciFen>, moves: Vec<UciMove>, chess960: bool) -> Result<(Board, Vec<Move>), String> {
+    let board = match fen {
+        Some(fen) => {
+            let fen = normalize_fen(fen.as_str())
+                .ok_or_else(|| format!("Invalid FEN {}: Expected 4 to 6 fields.", fen.as_str()))?;
+            Board::from_fen(&fen, chess960)
+                .map_err(|e| format!("Invalid FEN {}: {:?}", fen, e))?
+        }
+        None => Board::default()
+    };
+    let mut converted_moves = Vec::new();
+    let mut current_pos = board.clone();
+    for mv in moves {
+        let converted = mv.uci_move_into(&current_pos, chess960);
+        if !current_pos.is_legal(converted) {
+            return Err(format!("Illegal move {} in position {}.", mv, current_pos));
+        }
+        current_pos.play_unchecked(converted);
+        converted_moves.push(converted);
+    }
+    Ok((board, converted_moves))
+}
+
 enum Event {
     UciMessage(UciMessage),
-    EngineSearchUpdate(EngineSearchResult)
+    /// A message we recognized but couldn't make sense of.
+    InvalidMessage(String),
+    /// A search update, tagged with the ID of the search it came from.
+    EngineSearchUpdate(u64, EngineSearchResult)
 }
 
-fn parse_message(msg: &str) -> UciMessage {
+fn parse_message(msg: &str) -> Result<UciMessage, String> {
     fn parse_square(chars: &mut impl Iterator<Item=char>) -> Option<UciSquare> {
         let file = chars.next()?;
         if !('a'..='h').contains(&file) {
@@ -101,77 +224,125 @@ fn parse_message(msg: &str) -> UciMessage {
             rank
         })
     }
-    fn try_parse(msg: &str) -> Option<UciMessage> {
-        let mut parts = msg.split(' ');
+    fn parse_uci_move(mv: &str) -> Option<UciMove> {
+        let mut chars = mv.chars();
+        let from = parse_square(&mut chars)?;
+        let to = parse_square(&mut chars)?;
+        let promotion =
+            if let Some(p) = chars.next() {
+                Some(match p {
+                    'q' => UciPiece::Queen,
+                    'n' => UciPiece::Knight,
+                    'r' => UciPiece::Rook,
+                    'b' => UciPiece::Bishop,
+                    _ => return None
+                })
+            } else {
+                None
+            };
+        if chars.next().is_some() {
+            return None;
+        }
+        Some(UciMove {
+            from,
+            to,
+            promotion
+        })
+    }
+    // Returns `None` if the message isn't one we handle ourselves.
+    fn try_parse(msg: &str) -> Option<Result<UciMessage, String>> {
+        let mut parts = msg.split_whitespace().peekable();
         let kind = parts.next()?;
         if kind == "position" {
-            if parts.next()? != "fen" {
-                return None;
-            }
-            let mut fen = parts.next()?.to_owned();
-            for _ in 0..5 {
-                fen.push(' ');
-                fen.push_str(parts.next()?);
-            }
-            if Board::from_fen(&fen, true).is_err() {
-                return None;
-            }
-            let fen = UciFen(fen);
+            let (mut board, fen) = match parts.next()? {
+                "startpos" => (Board::default(), None),
+                "fen" => {
+                    let mut fen_fields = Vec::new();
+                    while let Some(field) = parts.next_if(|&part| part != "moves") {
+                        fen_fields.push(field);
+                    }
+                    let fen = normalize_fen(&fen_fields.join(" "))?;
+                    let board = Board::from_fen(&fen, false)
+                        .or_else(|_| Board::from_fen(&fen, true))
+                        .ok()?;
+                    (board, Some(UciFen(fen)))
+                }
+                _ => return None
+            };
 
             let mut moves = Vec::new();
             if parts.next() == Some("moves") {
                 for mv in parts {
-                    let mut chars = mv.chars();
-                    let from = parse_square(&mut chars)?;
-                    let to = parse_square(&mut chars)?;
-                    let promotion =
-                        if let Some(p) = chars.next() {
-                            Some(match p {
-                                'q' => UciPiece::Queen,
-                                'n' => UciPiece::Knight,
-                                'r' => UciPiece::Rook,
-                                'b' => UciPiece::Bishop,
-                                _ => return None
-                            })
-                        } else {
-                            None
-                        };
-                    if chars.next().is_some() {
-                        return None;
-                    }
-                    moves.push(UciMove {
-                        from,
-                        to,
-                        promotion
-                    });
+                    // Only fall back to SAN if the move isn't a UCI move.
+                    // Castling from SAN is sent as the king capturing its rook,
+                    // which is understood whether or not Chess960 is enabled.
+                    let (uci_mv, played) = match parse_uci_move(mv) {
+                        Some(uci_mv) => {
+                            // Chess960 isn't known yet, so accept either castling notation.
+                            let played = [true, false]
+                                .into_iter()
+                                .map(|chess960| uci_mv.uci_move_into(&board, chess960))
+                                .find(|&played| board.is_legal(played));
+                            let played = match played {
+                                Some(played) => played,
+                                None => return Some(Err(format!("Illegal move {} in position {}.", mv, board)))
+                            };
+                            (uci_mv, played)
+                        }
+                        None => match tantabus::san::san_to_move(&board, mv) {
+                            Some(played) => (played.uci_move_into(&board, true), played),
+                            None => return Some(Err(format!(
+                                "Invalid move {} in position {}: Not a UCI move or a legal and unambiguous SAN move.",
+                                mv,
+                                board
+                            )))
+                        }
+                    };
+                    board.play_unchecked(played);
+                    moves.push(uci_mv);
                 }
             }
-            return Some(UciMessage::Position {
-                startpos: false,
-                fen: Some(fen),
+            return Some(Ok(UciMessage::Position {
+                startpos: fen.is_none(),
+                fen,
                 moves
-            });
+            }));
         }
         None
     }
-    let mut msg = vampirc_uci::parse_one(msg);
+    let msg = vampirc_uci::parse_one(msg);
     if let UciMessage::Unknown(raw_msg, _) = &msg {
         if let Some(parsed) = try_parse(raw_msg) {
-            msg = parsed;
+            return parsed;
         }
     }
-    msg
+    Ok(msg)
 }
 
 fn main() {
-    if std::env::args().nth(1).as_deref() == Some("bench") {
-        bench::bench();
-        return;
+    match std::env::args().nth(1).as_deref() {
+        Some("bench") => {
+            bench::bench();
+            return;
+        }
+        Some("epd") => {
+            epd::epd(std::env::args().skip(2));
+            return;
+        }
+        _ => {}
     }
     
     let mut position: Option<(Board, Vec<Move>)> = None;
-    let mut search = None;
+    // The running search, which hands back the cache table and search memory when joined.
+    let mut search: Option<(Arc<AtomicBool>, JoinHandle<(CacheTable, SearchMemory)>)> = None;
+    let mut search_id = 0;
     let mut cache_table = None;
+    // Kept between moves of the same game, like the cache table.
+    let mut search_memory = None;
+    // Set when the hash size changes or a new game starts while a search holds the table,
+    // so what the search hands back is dropped rather than reused.
+    let mut discard_cache_table = false;
+    let mut discard_search_memory = false;
 
     let mut options = UciOptionsHandler::new();
 
@@ -181,8 +352,11 @@ fn main() {
         move || {
             let mut lines = BufReader::new(stdin()).lines();
             while let Some(Ok(line)) = lines.next() {
-                let msg = parse_message(&line);
-                let _ = event_sink.send(Event::UciMessage(msg));
+                let event = match parse_message(&line) {
+                    Ok(msg) => Event::UciMessage(msg),
+                    Err(error) => Event::InvalidMessage(error)
+                };
+                let _ = event_sink.send(event);
             }
             let _ = event_sink.send(Event::UciMessage(UciMessage::Quit));
         }
@@ -202,41 +376,95 @@ fn main() {
                 UciMessage::Debug(_) => {}
                 UciMessage::IsReady => send_message(UciMessage::ReadyOk),
                 UciMessage::SetOption { name, value } => {
-                    options.update(&name, value);
+                    if search.is_some() && name == "Threads" {
+                        send_error("Threads can't be changed during a search.".to_owned());
+                    } else if let Err(error) = options.update(&name, value) {
+                        send_error(error);
+                    } else if name == "Hash" {
+                        // Reallocate at the next search so the new size takes effect.
+                        // The size is reported when it's allocated.
+                        cache_table = None;
+                        if search.is_some() {
+                            discard_cache_table = true;
+                            let info = "Hash will be resized when the current search finishes.";
+                            send_message(UciMessage::info_string(info.to_owned()));
+                        }
+                    } else if name == "UCI_Opponent" {
+                        let info = match &options.options.opponent {
+                            Some(opponent) => format!(
+                                "Opponent: {} (title: {}, elo: {}, {})",
+                                opponent.name,
+                                opponent.title.as_deref().unwrap_or("none"),
+                                opponent.elo.map_or("none".to_owned(), |elo| elo.to_string()),
+                                if opponent.computer { "computer" } else { "human" }
+                            ),
+                            None => "WARNING: Could not parse UCI_Opponent.".to_owned()
+                        };
+                        send_message(UciMessage::info_string(info));
+                    }
+                }
+                UciMessage::UciNewGame => {
+                    cache_table = None;
+                    search_memory = None;
+                    discard_cache_table = search.is_some();
+                    discard_search_memory = search.is_some();
                 }
-                UciMessage::UciNewGame => cache_table = None,
     
                 UciMessage::Position { fen, moves, .. } => {
-                    let board: Board = fen
-                        .map(|fen| {
-                            Board::from_fen(fen.as_str(), options.options.chess960)
-                                .unwrap()
-                        })
-                        .unwrap_or_default();
-                    let mut converted_moves = Vec::new();
-                    let mut current_pos = board.clone();
-                    for mv in moves {
-                        let mv = mv.uci_move_into(&current_pos, options.options.chess960);
-                        current_pos.play_unchecked(mv);
-                        converted_moves.push(mv);
+                    match parse_position(fen, moves, options.options.chess960) {
+                        Ok(parsed) => position = Some(parsed),
+                        Err(error) => send_error(error)
                     }
-                    position = Some((board, converted_moves));
                 }
                 UciMessage::Go { time_control, search_control } => {
+                    // Only one search runs at a time so the cache table has one owner.
+                    // The interrupted search's remaining output is dropped as stale.
+                    if let Some((terminator, handle)) = search.take() {
+                        send_error("A search is already running. Stopping it first.".to_owned());
+                        terminator.store(true, Ordering::Release);
+                        let (table, memory) = handle.join().unwrap();
+                        if !std::mem::take(&mut discard_cache_table) {
+                            cache_table = Some(table);
+                        }
+                        if !std::mem::take(&mut discard_search_memory) {
+                            search_memory = Some(memory);
+                        }
+                    }
+                    search_id += 1;
+                    let (init_pos, moves) = position.get_or_insert_with(|| {
+                        let warn = "WARNING: No position was set. Searching from the starting position.";
+                        send_message(UciMessage::info_string(warn.to_owned()));
+                        (Board::default(), Vec::new())
+                    }).clone();
+                    // Play straight from the book unless we're analysing.
+                    let analysing = options.options.analyse_mode || matches!(
+                        time_control,
+                        None | Some(UciTimeControl::Infinite) | Some(UciTimeControl::Ponder)
+                    );
+                    if let (false, Some(book)) = (analysing, &options.options.book) {
+                        let board = current_board(&position);
+                        if let Some(mv) = book.pick(&board, clock_seed()) {
+                            send_message(UciMessage::info_string("Playing a book move".to_owned()));
+                            send_message(UciMessage::BestMove {
+                                best_move: mv.uci_move_into(&board, options.options.chess960),
+                                ponder: None
+                            });
+                            continue;
+                        }
+                    }
                     let time_manager = match time_control {
-                        Some(UciTimeControl::MoveTime(time)) => StandardTimeManager::Fixed(time.to_std().unwrap()),
+                        Some(UciTimeControl::MoveTime(time)) => StandardTimeManager::Fixed(time.to_std().unwrap_or_default()),
                         Some(UciTimeControl::TimeLeft {
                             white_time,
                             black_time,
                             white_increment,
                             black_increment,
-                            ..
+                            moves_to_go
                         }) => {
-                            let (initial_pos, moves) = position.as_ref().unwrap();
                             let side_to_move = if moves.len() % 2 == 0 {
-                                initial_pos.side_to_move()
+                                init_pos.side_to_move()
                             } else {
-                                !initial_pos.side_to_move()
+                                !init_pos.side_to_move()
                             };
                             let (time_left, increment) = match side_to_move {
                                 Color::White => (white_time, white_increment),
@@ -244,16 +472,25 @@ fn main() {
                             };
                             let time_left = time_left.and_then(|t| t.to_std().ok()).unwrap_or_default();
                             let increment = increment.and_then(|t| t.to_std().ok()).unwrap_or_default();
-                            StandardTimeManager::standard(time_left, increment)
+                            let moves_to_go = moves_to_go.map(u32::from);
+                            StandardTimeManager::standard(time_left, increment, moves_to_go)
+                        }
+                        Some(UciTimeControl::Ponder) => {
+                            let warn = "WARNING: go ponder is currently unimplemented. Searching until stopped.";
+                            send_message(UciMessage::info_string(warn.to_owned()));
+                            StandardTimeManager::Infinite
                         }
-                        Some(UciTimeControl::Ponder) => todo!("Ponder is unimplemented."),
                         None | Some(UciTimeControl::Infinite) => StandardTimeManager::Infinite
                     };
                     
                     options.options.engine_options.max_depth = 64u8.try_into().unwrap();
                     if let Some(search_control) = search_control {
                         if let Some(depth) = search_control.depth {
-                            options.options.engine_options.max_depth = depth.try_into().unwrap();
+                            if let Ok(depth) = depth.try_into() {
+                                options.options.engine_options.max_depth = depth;
+                            } else {
+                                send_error(format!("Invalid depth {}. Ignoring the depth limit.", depth));
+                            }
                         }
                         //TODO implement the rest
                         if let Some(_) = search_control.nodes {
@@ -269,7 +506,43 @@ fn main() {
                             send_message(UciMessage::info_string(warn.to_owned()));
                         }
                     }
-                    let (init_pos, moves) = position.clone().unwrap();
+                    let mut engine_options = options.options.engine_options.clone();
+                    if let Ok(available) = std::thread::available_parallelism() {
+                        let threads = engine_options.threads.get();
+                        if threads as usize > available.get() {
+                            let warn = if options.options.oversubscribe {
+                                format!("WARNING: Using {} threads, but only {} logical CPUs are available.", threads, available)
+                            } else {
+                                // Guard against a misconfigured GUI spawning thousands of threads.
+                                engine_options.threads = NonZeroU32::new(available.get() as u32).unwrap();
+                                format!(
+                                    "WARNING: Only {} logical CPUs are available. Set Oversubscribe to use {} threads anyway.",
+                                    available,
+                                    threads
+                                )
+                            };
+                            send_message(UciMessage::info_string(warn));
+                        }
+                    }
+                    let mut max_nodes = None;
+                    if options.options.limit_strength {
+                        let limit = StrengthLimit::from_elo(options.options.elo);
+                        engine_options.max_depth = engine_options.max_depth.min(limit.max_depth);
+                        max_nodes = Some(limit.max_nodes);
+                        // The node limit only counts the main thread's nodes, so helpers would play stronger.
+                        engine_options.threads = NonZeroU32::new(1).unwrap();
+                        engine_options.root_noise = Some(RootNoise {
+                            max: limit.noise,
+                            seed: clock_seed()
+                        });
+                    }
+                    let threads = engine_options.threads.get();
+                    let pinned = options.options.engine_options.pin_threads && cfg!(feature = "affinity");
+                    send_message(UciMessage::info_string(format!(
+                        "Searching with {} threads, {}",
+                        threads,
+                        if pinned { "pinned" } else { "unpinned" }
+                    )));
                     let terminator = Arc::new(AtomicBool::new(false));
                     let mut handler = UciHandler {
                         time_manager,
@@ -280,48 +553,86 @@ fn main() {
                         event_sink: event_sink.clone(),
                         total_nodes: 0,
                         prev_result: None,
+                        search_id,
+                        max_nodes
                     };
-                    std::thread::spawn({
-                        let cache_table_size = options.options.cache_table_size;
-                        let cache_table = cache_table
-                            .take()
-                            .unwrap_or_else(|| CacheTable::new_with_size(cache_table_size).unwrap());
-                        let engine_options = options.options.engine_options.clone();
+                    let cache_table = match cache_table.take() {
+                        Some(cache_table) => cache_table,
+                        None => {
+                            let cache_table = CacheTable::new_with_size(options.options.cache_table_size)
+                                .unwrap_or_else(|error| {
+                                    send_error(format!("Failed to allocate the hash table ({:?}). Using a single entry.", error));
+                                    CacheTable::new_with_entries(NonZeroU32::new(1).unwrap())
+                                });
+                            // The size is rounded down to a whole number of entries.
+                            send_message(UciMessage::info_string(format!(
+                                "Allocated a hash table of {} entries of {} bytes each ({} bytes)",
+                                cache_table.capacity(),
+                                CacheTable::entry_size(),
+                                cache_table.size()
+                            )));
+                            cache_table
+                        }
+                    };
+                    let memory = search_memory.take()
+                        .filter(|_| options.options.keep_history)
+                        .unwrap_or_default();
+                    let handle = std::thread::spawn({
                         let search_params = options.options.search_params.clone();
                         move || {
-                            let mut search_state = Engine::new(
+                            let mut search_state = Engine::new_with_memory(
                                 &mut handler,
                                 init_pos,
                                 moves,
                                 engine_options,
                                 search_params,
-                                cache_table
+                                cache_table,
+                                memory
                             );
                             search_state.search();
-                            let cache_table = search_state.into_cache_table();
-                            handler.finish(cache_table);
+                            let parts = search_state.into_parts();
+                            handler.finish();
+                            parts
                         }
                     });
-                    search = Some(terminator);
+                    search = Some((terminator, handle));
                 }
-                UciMessage::Stop => if let Some(search) = &mut search {
-                    search.store(true, Ordering::Release);
+                UciMessage::Stop => if let Some((terminator, _)) = &search {
+                    terminator.store(true, Ordering::Release);
                 },
 
                 UciMessage::PonderHit => {}
-                UciMessage::Quit => break 'main,
+                UciMessage::Quit => {
+                    // Wait for the search to wind down so nothing is printed after we exit.
+                    if let Some((terminator, handle)) = search.take() {
+                        terminator.store(true, Ordering::Release);
+                        let _ = handle.join();
+                    }
+                    break 'main;
+                }
                 UciMessage::Register { .. } => {}
-                UciMessage::Unknown(..) => {}
+                UciMessage::Unknown(raw_msg, _) => match raw_msg.trim() {
+                    "" => {}
+                    "fen" => {
+                        let board = current_board(&position);
+                        if options.options.chess960 {
+                            println!("{:#}", board);
+                        } else {
+                            println!("{}", board);
+                        }
+                        std::io::stdout().flush().unwrap();
+                    }
+                    raw_msg => send_error(format!("Unknown command: {}", raw_msg))
+                },
                 //Engine to GUI messages
                 _ => {}
             }
-            Event::EngineSearchUpdate(result) => match result {
+            Event::InvalidMessage(error) => send_error(error),
+            // Leftovers from a search that was interrupted by another.
+            Event::EngineSearchUpdate(id, _) if id != search_id => {}
+            Event::EngineSearchUpdate(_, result) => match result {
                 EngineSearchResult::SearchInfo(result, duration) => {
-                    let (board, moves) = position.as_ref().unwrap();
-                    let mut current_pos = board.clone();
-                    for &mv in moves {
-                        current_pos.play_unchecked(mv);
-                    }
+                    let mut current_pos = current_board(&position);
                     let mut principal_variation = Vec::new();
                     for mv in result.principal_variation {
                         let uci_mv = mv.uci_move_into(&current_pos, options.options.chess960);
@@ -329,11 +640,7 @@ fn main() {
                         current_pos.play_unchecked(mv);
                     }
                     send_message(UciMessage::Info(vec![
-                        match result.eval.kind() {
-                            EvalKind::Centipawn(cp) => UciInfoAttribute::from_centipawns(cp as i32),
-                            EvalKind::MateIn(m) => UciInfoAttribute::from_mate(((m + 1) / 2) as i8),
-                            EvalKind::MatedIn(m) => UciInfoAttribute::from_mate(-(((m + 1) / 2) as i8))
-                        },
+                        eval_to_uci_score(result.eval, result.bound),
                         UciInfoAttribute::Depth(result.depth),
                         UciInfoAttribute::SelDepth(result.seldepth),
                         UciInfoAttribute::Nodes(result.nodes),
@@ -342,16 +649,42 @@ fn main() {
                         UciInfoAttribute::HashFull(result.cache_approx_size_permill as u16)
                     ]));
                 }
-                EngineSearchResult::SearchFinished(result, cache) => {
-                    cache_table = Some(cache);
-                    let (board, moves) = position.as_ref().unwrap();
-                    let mut current_pos = board.clone();
-                    for &mv in moves {
-                        current_pos.play_unchecked(mv);
+                EngineSearchResult::SearchFinished(result, duration) => {
+                    if let Some((_, handle)) = search.take() {
+                        let (table, memory) = handle.join().unwrap();
+                        if !std::mem::take(&mut discard_cache_table) {
+                            cache_table = Some(table);
+                        }
+                        if !std::mem::take(&mut discard_search_memory) {
+                            search_memory = Some(memory);
+                        }
+                    }
+                    // Per-depth info lines don't carry NPS, so this summary
+                    // is never a repeat of the last line sent for the search.
+                    let nps = result.nodes * 1000 / (duration.as_millis() as u64).max(1);
+                    send_message(UciMessage::Info(vec![
+                        eval_to_uci_score(result.eval, result.bound),
+                        UciInfoAttribute::Nodes(result.nodes),
+                        UciInfoAttribute::Nps(nps),
+                        UciInfoAttribute::Time(vampirc_uci::Duration::from_std(duration).unwrap())
+                    ]));
+                    let current_pos = current_board(&position);
+                    if let Some(mv) = result.mv {
+                        let mv = mv.uci_move_into(&current_pos, options.options.chess960);
+                        let ponder = if options.options.ponder {
+                            ponder_move(&current_pos, &result, options.options.chess960)
+                        } else {
+                            None
+                        };
+                        send_message(UciMessage::BestMove {
+                            best_move: mv,
+                            ponder
+                        });
+                    } else {
+                        // There are no legal moves. vampirc-uci can't represent the null move.
+                        println!("bestmove 0000");
+                        std::io::stdout().flush().unwrap();
                     }
-                    let mv = result.mv.uci_move_into(&current_pos, options.options.chess960);
-                    send_message(UciMessage::best_move(mv));
-                    search = None;
                 }
             }
         }
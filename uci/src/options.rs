@@ -1,19 +1,74 @@
+use std::error::Error;
+
 use indexmap::IndexMap;
 use tantabus::search::{EngineOptions, SearchParams};
 use vampirc_uci::UciOptionConfig;
 
+use crate::book::Book;
+use crate::strength::{MIN_ELO, MAX_ELO};
+
+/// The opponent as described by `UCI_Opponent`.
+/// The format is `<title> <elo> <computer|human> <name>`,
+/// where the title and elo may be `none`.
+#[derive(Debug, Clone)]
+pub struct Opponent {
+    pub title: Option<String>,
+    pub elo: Option<u32>,
+    pub computer: bool,
+    pub name: String
+}
+
+impl Opponent {
+    fn parse(value: &str) -> Option<Self> {
+        let mut parts = value.split_whitespace();
+        let title = match parts.next()? {
+            "none" => None,
+            title => Some(title.to_owned())
+        };
+        let elo = match parts.next()? {
+            "none" => None,
+            elo => Some(elo.parse().ok()?)
+        };
+        let computer = match parts.next()? {
+            "computer" => true,
+            "human" => false,
+            _ => return None
+        };
+        let name = parts.collect::<Vec<_>>().join(" ");
+        Some(Self {
+            title,
+            elo,
+            computer,
+            name
+        })
+    }
+}
+
 pub struct UciOptions {
     pub engine_options: EngineOptions,
     pub search_params: SearchParams,
     pub cache_table_size: usize,
-    pub chess960: bool
+    pub chess960: bool,
+    pub ponder: bool,
+    pub opponent: Option<Opponent>,
+    /// Carry the history heuristics over between moves of the same game, aging them each move.
+    pub keep_history: bool,
+    /// Allow more threads than the machine can run at once.
+    pub oversubscribe: bool,
+    /// Weaken the engine to play at roughly `elo`.
+    pub limit_strength: bool,
+    pub elo: u32,
+    /// The opening book set by `BookFile`, if any.
+    pub book: Option<Book>,
+    /// Set by the GUI when analysing, in which case the book isn't used.
+    pub analyse_mode: bool
 }
 
-type Handler = Box<dyn Fn(&mut UciOptions, String)>;
+type Handler = Box<dyn Fn(&mut UciOptions, String) -> Result<(), Box<dyn Error>>>;
 
-trait Tunable {
+trait Tunable: Sized {
     fn to_tune_value(self) -> i64;
-    fn from_tune_value(value: i64) -> Self;
+    fn from_tune_value(value: i64) -> Result<Self, Box<dyn Error>>;
 }
 
 macro_rules! impl_tunable {
@@ -23,21 +78,35 @@ macro_rules! impl_tunable {
                 self as i64
             }
 
-            fn from_tune_value(value: i64) -> Self {
-                value as _
+            fn from_tune_value(value: i64) -> Result<Self, Box<dyn Error>> {
+                Ok(value.try_into()?)
             }
         }
     )*}
 }
-impl_tunable!(i8, u8, i16, u16, i32, u32);
+impl_tunable!(i8, u8, i16, u16, i32, u32, usize);
+
+impl Tunable for bool {
+    fn to_tune_value(self) -> i64 {
+        self as i64
+    }
+
+    fn from_tune_value(value: i64) -> Result<Self, Box<dyn Error>> {
+        match value {
+            0 => Ok(false),
+            1 => Ok(true),
+            _ => Err(format!("Expected 0 or 1, got {}", value).into())
+        }
+    }
+}
 
 impl Tunable for f32 {
     fn to_tune_value(self) -> i64 {
         (self * 1000.0).round() as i64
     }
 
-    fn from_tune_value(value: i64) -> Self {
-        value as f32 / 1000.0
+    fn from_tune_value(value: i64) -> Result<Self, Box<dyn Error>> {
+        Ok(value as f32 / 1000.0)
     }
 }
 
@@ -54,7 +123,15 @@ impl UciOptionsHandler {
             engine_options: EngineOptions::default(),
             search_params: SearchParams::default(),
             cache_table_size: 16 * MEGABYTE,
-            chess960: false
+            chess960: false,
+            ponder: false,
+            opponent: None,
+            keep_history: true,
+            oversubscribe: false,
+            limit_strength: false,
+            elo: 1500,
+            book: None,
+            analyse_mode: false
         };
         let mut handlers = IndexMap::new();
         macro_rules! add_handlers {
@@ -78,9 +155,15 @@ impl UciOptionsHandler {
                 name: "UCI_Chess960".to_owned(),
                 default: Some(options.chess960)
             } => |options, value| {
-                options.chess960 = value
-                    .parse()
-                    .unwrap();
+                options.chess960 = value.parse()?;
+                Ok(())
+            }
+            UciOptionConfig::Check {
+                name: "Ponder".to_owned(),
+                default: Some(options.ponder)
+            } => |options, value| {
+                options.ponder = value.parse()?;
+                Ok(())
             }
             UciOptionConfig::Spin {
                 name: "Hash".to_owned(),
@@ -88,10 +171,8 @@ impl UciOptionsHandler {
                 min: Some(0),
                 max: Some(64_000) //64 Gigabytes
             } => |options, value| {
-                options.cache_table_size = value
-                    .parse::<usize>()
-                    .unwrap()
-                    * MEGABYTE;
+                options.cache_table_size = value.parse::<usize>()? * MEGABYTE;
+                Ok(())
             }
             UciOptionConfig::Spin {
                 name: "Threads".to_owned(),
@@ -99,7 +180,77 @@ impl UciOptionsHandler {
                 min: Some(1),
                 max: Some(4096)
             } => |options, value| {
-                options.engine_options.threads = value.parse().unwrap();
+                options.engine_options.threads = value.parse()?;
+                Ok(())
+            }
+            UciOptionConfig::Check {
+                name: "KeepHistory".to_owned(),
+                default: Some(options.keep_history)
+            } => |options, value| {
+                options.keep_history = value.parse()?;
+                Ok(())
+            }
+            UciOptionConfig::Check {
+                name: "Oversubscribe".to_owned(),
+                default: Some(options.oversubscribe)
+            } => |options, value| {
+                options.oversubscribe = value.parse()?;
+                Ok(())
+            }
+            UciOptionConfig::Check {
+                name: "PinThreads".to_owned(),
+                default: Some(options.engine_options.pin_threads)
+            } => |options, value| {
+                options.engine_options.pin_threads = value.parse()?;
+                Ok(())
+            }
+            UciOptionConfig::Check {
+                name: "BreakRootTies".to_owned(),
+                default: Some(options.engine_options.break_root_ties)
+            } => |options, value| {
+                options.engine_options.break_root_ties = value.parse()?;
+                Ok(())
+            }
+            UciOptionConfig::Check {
+                name: "UCI_LimitStrength".to_owned(),
+                default: Some(options.limit_strength)
+            } => |options, value| {
+                options.limit_strength = value.parse()?;
+                Ok(())
+            }
+            UciOptionConfig::Spin {
+                name: "UCI_Elo".to_owned(),
+                default: Some(options.elo as i64),
+                min: Some(MIN_ELO as i64),
+                max: Some(MAX_ELO as i64)
+            } => |options, value| {
+                options.elo = value.parse()?;
+                Ok(())
+            }
+            UciOptionConfig::String {
+                name: "UCI_Opponent".to_owned(),
+                default: None
+            } => |options, value| {
+                options.opponent = Opponent::parse(&value);
+                Ok(())
+            }
+            UciOptionConfig::String {
+                name: "BookFile".to_owned(),
+                default: Some("<empty>".to_owned())
+            } => |options, value| {
+                // A Polyglot book. Empty means no book.
+                options.book = match value.trim() {
+                    "" | "<empty>" => None,
+                    path => Some(Book::open(path)?)
+                };
+                Ok(())
+            }
+            UciOptionConfig::Check {
+                name: "UCI_AnalyseMode".to_owned(),
+                default: Some(options.analyse_mode)
+            } => |options, value| {
+                options.analyse_mode = value.parse()?;
+                Ok(())
             }
         }
         macro_rules! add_search_param_handlers {
@@ -111,28 +262,54 @@ impl UciOptionsHandler {
                         min: Some(i32::MIN as i64),
                         max: Some(i32::MAX as i64)
                     } => |options, value| {
-                        let value = value.parse().unwrap();
-                        options.search_params.$($field)* = Tunable::from_tune_value(value);
+                        let value = value.parse()?;
+                        options.search_params.$($field)* = Tunable::from_tune_value(value)?;
+                        Ok(())
                     })*
                 }
             }
         }
         // Modify for exposing search params for tuning
         add_search_param_handlers! {
-            // [lmr.min_depth]
-            // [lmr.base_reduction]
-            // [lmr.div]
-            // [lmr.history_reduction_div]
-            // [nmp.base_reduction]
-            // [nmp.margin_div]
-            // [nmp.margin_max_reduction]
-            // [lmp.quiets_to_check[0]]
-            // [lmp.quiets_to_check[1]]
-            // [lmp.quiets_to_check[2]]
-            // [fp.margins[0]]
-            // [fp.margins[1]]
-            // [rfp.base_margin]
-            // [rfp.max_depth]
+            [lmr.min_depth]
+            [lmr.base_reduction]
+            [lmr.div]
+            [lmr.history_reduction_div]
+            [nmp.base_reduction]
+            [nmp.div_reduction_divisor]
+            [nmp.margin_div]
+            [nmp.margin_max_reduction]
+            [nmp.verify_min_depth]
+            [nmp.min_non_pawn_material]
+            [lmp.quiets_to_check[0]]
+            [lmp.quiets_to_check[1]]
+            [lmp.quiets_to_check[2]]
+            [lmp.base]
+            [lmp.factor]
+            [lmp.max_depth]
+            [lmp.history_weighted]
+            [lmp.bad_history]
+            [lmp.bad_history_cost]
+            [lmp.good_history]
+            [fp.base_margin]
+            [fp.per_depth_margin]
+            [fp.max_depth]
+            [rfp.base_margin]
+            [rfp.max_depth]
+            [iir.min_depth]
+            [iir.reduction]
+            [see.losing_capture_reduction_threshold]
+            [see.losing_capture_reduction]
+            [see.slightly_losing_capture_reduction]
+            [see.history_reduction_div]
+            [qsearch.delta_pruning]
+            [qsearch.delta_margin]
+            [qsearch.max_ply]
+            [qsearch.check_plies]
+            [history.gravity]
+            [aspiration.initial_delta]
+            [aspiration.widen_factor]
+            [aspiration.max_researches]
         }
 
         Self {
@@ -141,9 +318,13 @@ impl UciOptionsHandler {
         }
     }
 
-    pub fn update(&mut self, key: &str, value: Option<String>) {
+    /// Update an option. Unknown options are ignored.
+    pub fn update(&mut self, key: &str, value: Option<String>) -> Result<(), String> {
         if let Some((_, handler)) = self.handlers.get(key) {
-            handler(&mut self.options, value.unwrap())
+            let value = value.ok_or_else(|| format!("Missing value for option {}.", key))?;
+            handler(&mut self.options, value)
+                .map_err(|e| format!("Invalid value for option {}: {}", key, e))?;
         }
+        Ok(())
     }
 }
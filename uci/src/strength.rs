@@ -0,0 +1,55 @@
+use std::convert::TryInto;
+use std::num::NonZeroU8;
+
+pub const MIN_ELO: u32 = 1000;
+pub const MAX_ELO: u32 = 2800;
+
+/// Search limits that weaken the engine to play at roughly some Elo.
+///
+/// The Elo is interpolated between two anchors:
+/// - At [`MIN_ELO`], the engine searches to depth 1, at most 256 nodes,
+///   with up to 300 centipawns of noise on each root move.
+/// - At [`MAX_ELO`], it searches to depth 12, at most about a million nodes,
+///   with no noise.
+///
+/// The depth grows linearly, the node limit exponentially, and the noise
+/// falls off quadratically in between. The calibration is approximate and
+/// only meant to be monotonic, so don't expect it to line up with any rating list.
+#[derive(Debug, Clone)]
+pub struct StrengthLimit {
+    pub max_depth: NonZeroU8,
+    pub max_nodes: u64,
+    /// The most centipawns added to a root move's eval when picking the move to play.
+    pub noise: u16
+}
+
+impl StrengthLimit {
+    pub fn from_elo(elo: u32) -> Self {
+        let elo = elo.clamp(MIN_ELO, MAX_ELO);
+        let strength = (elo - MIN_ELO) as f32 / (MAX_ELO - MIN_ELO) as f32;
+        let weakness = 1.0 - strength;
+        Self {
+            max_depth: (1 + (strength * 11.0).round() as u8).try_into().unwrap(),
+            max_nodes: 2f32.powf(8.0 + strength * 12.0) as u64,
+            noise: (300.0 * weakness * weakness).round() as u16
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn monotonic() {
+        let mut prev = StrengthLimit::from_elo(MIN_ELO);
+        for elo in MIN_ELO + 1..=MAX_ELO {
+            let limit = StrengthLimit::from_elo(elo);
+            assert!(limit.max_depth >= prev.max_depth, "depth falls at {} Elo", elo);
+            assert!(limit.max_nodes >= prev.max_nodes, "nodes fall at {} Elo", elo);
+            assert!(limit.noise <= prev.noise, "noise rises at {} Elo", elo);
+            prev = limit;
+        }
+        assert_eq!(prev.noise, 0);
+    }
+}